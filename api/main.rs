@@ -1,14 +1,47 @@
 use base64::{engine::general_purpose, Engine as _};
-use image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, load_from_memory};
-use std::path::Path;
-use serde_json::json;
-use serde::{Deserialize,Serialize};
-use vercel_runtime::{run, Body, Error, Request, RequestPayloadExt, Response, StatusCode, http::bad_request};
+use dithering::cmyk::INKS;
+use dithering::palette::named;
+use dithering::{Algorithm, ColorMode, ParamError, ParamKind, ParamSpec, Params, Rendered};
+use image::{DynamicImage, ImageError, ImageFormat, ImageOutputFormat};
+use image::io::Reader as ImageReader;
+use std::fmt;
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use serde_json::{error::Category, json};
+use serde::{Deserialize, Serialize};
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+// Largest decoded upload we accept, in bytes
+const MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+// Largest image we accept, in pixels
+const MAX_PIXELS: u64 = 40_000_000;
+// Newest request format; version 2 dithers in linear light unless told otherwise
+const API_VERSION: u32 = 2;
+
+// Version 1 integrations were tuned against dithering on sRGB values, so they keep that unless they ask
+fn linear_light_default(version: u32) -> bool {
+    version >= 2
+}
 
 #[derive(Deserialize)]
 struct Input {
-    alg_type: String,
+    alg_type: Algorithm,
     image: String,
+    #[serde(default)]
+    params: Params,
+    // Requests without a version predate linear light and are treated as version 1
+    api_version: Option<u32>,
+}
+
+struct Output {
+    // Either one image, or one per CMYK ink
+    image: Option<String>,
+    separations: Vec<(&'static str, String)>,
+    // Hex colours the image was dithered to and how many pixels use each, for palette output
+    palette: Option<(Vec<String>, Vec<u64>)>,
+    width: u32,
+    height: u32,
+    algorithm: Algorithm,
 }
 
 #[tokio::main]
@@ -17,187 +50,431 @@ async fn main() -> Result<(), Error> {
 }
 
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let body = req.body();
-    let input: Input = match serde_json::from_slice(body) {
-        Ok(input) => input,
-        Err(_) => return err(),
+    if req.method().as_str() == "GET" {
+        return algorithms();
+    }
+
+    let output = match process(req.body()) {
+        Ok(output) => output,
+        Err(e) => return e.into_response(),
     };
-    let decoded_bytes = general_purpose::STANDARD.decode(input.image);
-    //let img = load_from_memory(&decoded_bytes);
-    //let edited_image: &ImageBuffer<Luma<u8>, Vec<u8>> = & select_algorithm(req.into_body().get);
+
+    let mut body = json!({
+      "width": output.width,
+      "height": output.height,
+      "algorithm": output.algorithm,
+    });
+    if let Some(image) = output.image {
+        body["image"] = image.into();
+    }
+    if let Some((colors, counts)) = output.palette {
+        body["palette"] = colors.into();
+        body["palette_counts"] = counts.into();
+    }
+    if !output.separations.is_empty() {
+        body["separations"] = output
+            .separations
+            .into_iter()
+            .map(|(ink, image)| (ink.to_string(), image.into()))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(body.to_string().into())?)
+}
+
+// Discovery: every algorithm with its parameters, so clients can build their controls,
+// and the built-in palettes
+fn algorithms() -> Result<Response<Body>, Error> {
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .body(
             json!({
-              "message": "你好，世界"
+              "api_version": API_VERSION,
+              "algorithms": Algorithm::ALL.iter().map(|&alg| describe_algorithm(alg)).collect::<Vec<_>>(),
+              "palettes": named::ALL.iter().map(describe_palette).collect::<Vec<_>>(),
             })
             .to_string()
             .into(),
         )?)
 }
 
-fn err() -> Result<Response<Body>, Error> {
-    bad_request(APIError {
-        message: "Invalid payload",
-        code: "invalid_payload",
+fn describe_algorithm(alg: Algorithm) -> serde_json::Value {
+    json!({
+        "name": alg.name(),
+        "aliases": alg.aliases(),
+        "description": alg.description(),
+        "params": alg.params().iter().map(describe_param).collect::<Vec<_>>(),
+        "output_modes": alg.output_modes().iter().map(|mode| mode.name()).collect::<Vec<_>>(),
     })
 }
 
-#[derive(Serialize)]
-pub struct APIError {
-    pub message: &'static str,
-    pub code: &'static str,
+fn describe_palette(palette: &named::NamedPalette) -> serde_json::Value {
+    json!({
+        "name": palette.name,
+        "description": palette.description,
+        "colors": palette.colors.iter().map(|rgb| format!("#{:06x}", rgb)).collect::<Vec<_>>(),
+    })
 }
 
-// Convert an image to grayscale
-fn to_grayscale(img: &DynamicImage) -> GrayImage {
-    img.to_luma8()
-}
-
-// Save image
-fn save_image<P: AsRef<Path>>(img: &GrayImage, path: P) {
-    img.save(path).expect("Failed to save image");
-}
-
-// Floyd-Steinberg Dithering
-fn floyd_steinberg_dither(img: &GrayImage) -> GrayImage {
-    let (width, height) = img.dimensions();
-    let mut img_buf = img.clone();
-
-    for y in 0..height {
-        for x in 0..width {
-            let old_pixel = img_buf.get_pixel(x, y)[0] as i16;
-            let new_pixel = if old_pixel < 128 { 0 } else { 255 };
-            let error = old_pixel - new_pixel;
-            img_buf.put_pixel(x, y, Luma([new_pixel as u8]));
-
-            for (dx, dy, factor) in [
-                (1, 0, 7.0 / 16.0),
-                (-1, 1, 3.0 / 16.0),
-                (0, 1, 5.0 / 16.0),
-                (1, 1, 1.0 / 16.0),
-            ] {
-                let nx = x as i32 + dx;
-                let ny = y as i32 + dy;
-                if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                    let pos = (nx as u32, ny as u32);
-                    let neighbor_val = img_buf.get_pixel(pos.0, pos.1)[0] as f32;
-                    let new_val = (neighbor_val + (error as f32 * factor)).clamp(0.0, 255.0);
-                    img_buf.put_pixel(pos.0, pos.1, Luma([new_val as u8]));
-                }
-            }
+fn describe_param(spec: &ParamSpec) -> serde_json::Value {
+    let mut described = json!({
+        "name": spec.name,
+        "description": spec.description,
+    });
+    let fields = match spec.kind {
+        ParamKind::Integer { min, max, default } => json!({ "type": "integer", "min": min, "max": max, "default": default }),
+        ParamKind::Number { min, max, default } => json!({ "type": "number", "min": min, "max": max, "default": default }),
+        ParamKind::Boolean { default } => json!({ "type": "boolean", "default": default }),
+        ParamKind::Choice { values, default } => json!({ "type": "integer", "values": values, "default": default }),
+        ParamKind::Kernel { max_size } => json!({ "type": "kernel", "max_rows": max_size, "max_cols": max_size }),
+        ParamKind::ThresholdMap { max_size } => json!({ "type": "threshold_map", "max_width": max_size, "max_height": max_size }),
+        ParamKind::Offset { max } => json!({ "type": "offset", "min": -(max as i64), "max": max, "default": [0, 0] }),
+        ParamKind::Name { values, default } => json!({ "type": "string", "values": values, "default": default }),
+        ParamKind::Levels { max } => json!({ "type": "levels", "min": 2, "max": max, "default": 2 }),
+        ParamKind::Palette { max_size } => json!({
+            "type": "palette",
+            "min_colors": 2,
+            "max_colors": max_size,
+            "named": named::ALL.iter().map(|palette| palette.name).collect::<Vec<_>>(),
+        }),
+        ParamKind::Grayscale { values, default } => json!({
+            "type": "grayscale",
+            "values": values,
+            "default": default,
+            "weights": ["red", "green", "blue"],
+        }),
+        ParamKind::Curve { max_points } => json!({ "type": "curve", "min_points": 2, "max_points": max_points }),
+    };
+    if let (Some(described), serde_json::Value::Object(fields)) = (described.as_object_mut(), fields) {
+        described.extend(fields);
+    }
+    // The default hangs on the request's api_version, and a request without one gets version 1's
+    if spec.name == "linear_light" {
+        let by_version: serde_json::Map<_, _> =
+            (1..=API_VERSION).map(|version| (version.to_string(), json!(linear_light_default(version)))).collect();
+        described["default"] = json!(linear_light_default(1));
+        described["default_by_api_version"] = by_version.into();
+    }
+    described
+}
+
+// Run the whole pipeline: parse, decode, validate, dither, encode
+fn process(body: &[u8]) -> Result<Output, PipelineError> {
+    let mut input = parse_input(body)?;
+    let version = input.api_version.unwrap_or(1);
+    if !(1..=API_VERSION).contains(&version) {
+        return Err(PipelineError::Validation {
+            field: Some("api_version".to_string()),
+            detail: format!("must be between 1 and {}", API_VERSION),
+        });
+    }
+    // CMYK plates have no linear-light mode to turn off
+    if !linear_light_default(version) && input.params.color_mode != Some(ColorMode::Cmyk) {
+        input.params.linear_light.get_or_insert(false);
+    }
+    let pipeline = input.alg_type.pipeline(&input.params)?;
+    let decoded_bytes = decode_base64(&input.image)?;
+    let img = decode_image(&decoded_bytes)?;
+
+    let rendered = panic::catch_unwind(AssertUnwindSafe(|| pipeline.run(&img)))
+        .map_err(|_| PipelineError::Processing(format!("algorithm `{}` failed", input.alg_type)))?;
+    let (width, height) = rendered.dimensions();
+
+    let mut palette = None;
+    let (image, separations) = match rendered {
+        Rendered::Gray(img) => (Some(encode_png(DynamicImage::ImageLuma8(img))?), Vec::new()),
+        Rendered::Rgb(img) => (Some(encode_png(DynamicImage::ImageRgb8(img))?), Vec::new()),
+        Rendered::Paletted { image, palette: colors } => {
+            palette = Some((colors.to_hex(), colors.usage(&image)));
+            (Some(encode_png(DynamicImage::ImageRgb8(image))?), Vec::new())
+        }
+        Rendered::Separations(plates) => {
+            let encoded = INKS
+                .into_iter()
+                .zip(plates)
+                .map(|(ink, plate)| Ok((ink, encode_png(DynamicImage::ImageLuma8(plate))?)))
+                .collect::<Result<Vec<_>, PipelineError>>()?;
+            (None, encoded)
         }
+    };
+
+    Ok(Output {
+        image,
+        separations,
+        palette,
+        width,
+        height,
+        algorithm: input.alg_type,
+    })
+}
+
+fn encode_png(img: DynamicImage) -> Result<String, PipelineError> {
+    let mut png_bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut png_bytes, ImageOutputFormat::Png)
+        .map_err(|e| PipelineError::Encode(e.to_string()))?;
+    Ok(general_purpose::STANDARD.encode(&png_bytes))
+}
+
+fn parse_input(body: &[u8]) -> Result<Input, PipelineError> {
+    let de = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        // serde_json ends every message with where it stopped reading, which `field` says better
+        let message = inner.to_string();
+        let position = format!(" at line {} column {}", inner.line(), inner.column());
+        let detail = message.strip_suffix(&position).unwrap_or(&message).to_string();
+        // serde reports missing fields against the parent, so pull the name out of the message
+        let field = if path != "." {
+            Some(path)
+        } else {
+            detail
+                .strip_prefix("missing field `")
+                .and_then(|rest| rest.split('`').next())
+                .map(str::to_string)
+        };
+        // Well-formed JSON with the wrong content is a validation failure, not a parse failure
+        match inner.classify() {
+            Category::Data => PipelineError::Validation { field, detail },
+            _ => PipelineError::Parse { field, detail },
+        }
+    })
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, PipelineError> {
+    if data.is_empty() {
+        return Err(PipelineError::Validation {
+            field: Some("image".to_string()),
+            detail: "image must not be empty".to_string(),
+        });
+    }
+    // Reject before decoding: base64 expands by 4/3
+    if data.len() / 4 * 3 > MAX_UPLOAD_BYTES {
+        return Err(PipelineError::PayloadTooLarge {
+            detail: format!("upload exceeds {} bytes", MAX_UPLOAD_BYTES),
+        });
+    }
+    general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| PipelineError::Base64(e.to_string()))
+}
+
+fn decode_image(bytes: &[u8]) -> Result<DynamicImage, PipelineError> {
+    let format = image::guess_format(bytes)
+        .map_err(|_| PipelineError::UnsupportedFormat("unrecognised image format".to_string()))?;
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::Bmp | ImageFormat::Tiff | ImageFormat::WebP
+    ) {
+        return Err(PipelineError::UnsupportedFormat(format!("{:?} images are not supported", format)));
     }
 
-    img_buf
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(PipelineError::from)?;
+    if width == 0 || height == 0 {
+        return Err(PipelineError::Validation {
+            field: Some("image".to_string()),
+            detail: "image has no pixels".to_string(),
+        });
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(PipelineError::PayloadTooLarge {
+            detail: format!("{}x{} exceeds {} pixels", width, height, MAX_PIXELS),
+        });
+    }
+
+    image::load_from_memory_with_format(bytes, format).map_err(PipelineError::from)
+}
+
+// Every way a request can fail, one variant per pipeline stage
+#[derive(Debug)]
+pub enum PipelineError {
+    Parse { field: Option<String>, detail: String },
+    Base64(String),
+    UnsupportedFormat(String),
+    Decode(String),
+    PayloadTooLarge { detail: String },
+    Validation { field: Option<String>, detail: String },
+    Processing(String),
+    Encode(String),
 }
 
-// Bayer Ordered Dithering (4x4 Matrix)
-const BAYER4: [[u8; 4]; 4] = [
-    [15, 135, 45, 165],
-    [195, 75, 225, 105],
-    [60, 180, 30, 150],
-    [240, 120, 210, 90],
-];
+impl PipelineError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PipelineError::Parse { .. } | PipelineError::Base64(_) => StatusCode::BAD_REQUEST,
+            PipelineError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            PipelineError::UnsupportedFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            PipelineError::Decode(_) | PipelineError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            PipelineError::Processing(_) | PipelineError::Encode(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            PipelineError::Parse { .. } => "invalid_json",
+            PipelineError::Base64(_) => "invalid_base64",
+            PipelineError::UnsupportedFormat(_) => "unsupported_format",
+            PipelineError::Decode(_) => "undecodable_image",
+            PipelineError::PayloadTooLarge { .. } => "payload_too_large",
+            PipelineError::Validation { .. } => "validation_failed",
+            PipelineError::Processing(_) => "processing_failed",
+            PipelineError::Encode(_) => "encode_failed",
+        }
+    }
 
-fn ordered_dither(img: &GrayImage) -> GrayImage {
-    let (width, height) = img.dimensions();
-    let mut dithered = GrayImage::new(width, height);
+    pub fn message(&self) -> &'static str {
+        match self {
+            PipelineError::Parse { .. } => "Request body is not valid JSON for this endpoint",
+            PipelineError::Base64(_) => "Image is not valid base64",
+            PipelineError::UnsupportedFormat(_) => "Image format is not supported",
+            PipelineError::Decode(_) => "Image could not be decoded",
+            PipelineError::PayloadTooLarge { .. } => "Image is too large",
+            PipelineError::Validation { .. } => "Request failed validation",
+            PipelineError::Processing(_) => "Dithering failed",
+            PipelineError::Encode(_) => "Result could not be encoded",
+        }
+    }
 
-    for y in 0..height {
-        for x in 0..width {
-            let threshold = BAYER4[(y % 4) as usize][(x % 4) as usize];
-            let pixel = img.get_pixel(x, y)[0];
-            let new_val = if pixel > threshold { 255 } else { 0 };
-            dithered.put_pixel(x, y, Luma([new_val]));
+    fn field(&self) -> Option<&str> {
+        match self {
+            PipelineError::Parse { field, .. } | PipelineError::Validation { field, .. } => field.as_deref(),
+            _ => None,
         }
     }
 
-    dithered
-}
-
-// Atkinson Dithering
-fn atkinson_dither(img: &GrayImage) -> GrayImage {
-    let (width, height) = img.dimensions();
-    let mut img_buf = img.clone();
-
-    for y in 0..height {
-        for x in 0..width {
-            let old_pixel = img_buf.get_pixel(x, y)[0] as i16;
-            let new_pixel = if old_pixel < 128 { 0 } else { 255 };
-            let error = (old_pixel - new_pixel) / 8;
-            img_buf.put_pixel(x, y, Luma([new_pixel as u8]));
-
-            for (dx, dy) in [
-                (1, 0), (2, 0),
-                (-1, 1), (0, 1), (1, 1),
-                (0, 2),
-            ] {
-                let nx = x as i32 + dx;
-                let ny = y as i32 + dy;
-                if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                    let pos = (nx as u32, ny as u32);
-                    let neighbor_val = img_buf.get_pixel(pos.0, pos.1)[0] as i16;
-                    let new_val = (neighbor_val + error).clamp(0, 255);
-                    img_buf.put_pixel(pos.0, pos.1, Luma([new_val as u8]));
-                }
-            }
+    fn detail(&self) -> &str {
+        match self {
+            PipelineError::Parse { detail, .. }
+            | PipelineError::PayloadTooLarge { detail }
+            | PipelineError::Validation { detail, .. } => detail,
+            PipelineError::Base64(detail)
+            | PipelineError::UnsupportedFormat(detail)
+            | PipelineError::Decode(detail)
+            | PipelineError::Processing(detail)
+            | PipelineError::Encode(detail) => detail,
         }
     }
 
-    img_buf
+    fn into_response(self) -> Result<Response<Body>, Error> {
+        let body = APIError {
+            message: self.message(),
+            code: self.code(),
+            field: self.field(),
+            detail: Some(self.detail()).filter(|d| !d.is_empty()),
+        };
+        Ok(Response::builder()
+            .status(self.status())
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&body)?.into())?)
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.detail())
+    }
 }
 
-// Basic Threshold
-fn threshold_dither(img: &GrayImage, threshold: u8) -> GrayImage {
-    let (width, height) = img.dimensions();
-    let mut dithered = GrayImage::new(width, height);
+impl std::error::Error for PipelineError {}
 
-    for y in 0..height {
-        for x in 0..width {
-            let pixel = img.get_pixel(x, y)[0];
-            let new_val = if pixel > threshold { 255 } else { 0 };
-            dithered.put_pixel(x, y, Luma([new_val]));
+impl From<ParamError> for PipelineError {
+    fn from(e: ParamError) -> Self {
+        PipelineError::Validation {
+            field: Some(format!("params.{}", e.param)),
+            detail: e.detail,
         }
     }
-
-    dithered
 }
 
-fn select_algorithm(alg_type: &str, img: ImageBuffer<Luma<u8>, Vec<u8>>) -> GrayImage {
-    match alg_type {
-        "floyd-steinberg" => {
-            floyd_steinberg_dither(&img)
-        },
-        "ordered" => {
-            ordered_dither(&img)
-        },
-        "atkinson" => {
-            atkinson_dither(&img)
-        },
-        _ => {
-            threshold_dither(&img, 128)
+impl From<ImageError> for PipelineError {
+    fn from(e: ImageError) -> Self {
+        match e {
+            ImageError::Unsupported(_) => PipelineError::UnsupportedFormat(e.to_string()),
+            ImageError::Limits(_) => PipelineError::PayloadTooLarge { detail: e.to_string() },
+            _ => PipelineError::Decode(e.to_string()),
         }
     }
 }
 
-/* 
-fn main() {
-    let input_path: &str = "input/iStock-884221008.jpg";
-    let base_img: DynamicImage = image::open(input_path).expect("Failed to load image");
-    let gray: ImageBuffer<Luma<u8>, Vec<u8>> = to_grayscale(&base_img);
+#[derive(Serialize)]
+pub struct APIError<'a> {
+    pub message: &'static str,
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<&'a str>,
+}
 
-    let  alg_type = "OK";
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    select_algorithm(alg_type,gray);
+    fn failure(body: &[u8]) -> PipelineError {
+        match process(body) {
+            Ok(_) => panic!("{} was accepted", String::from_utf8_lossy(body)),
+            Err(e) => e,
+        }
+    }
 
-    /* 
-    save_image(&floyd_steinberg_dither(&gray), "output/floydsteinberg.png");
-    save_image(&ordered_dither(&gray), "output/ordered.png");
-    save_image(&atkinson_dither(&gray), "output/atkinson.png");
-    save_image(&threshold_dither(&gray, 128), "output/threshold.png");
-    */
-}
-*/
+    fn request(image: &str) -> Vec<u8> {
+        json!({ "alg_type": "floyd-steinberg", "image": image }).to_string().into_bytes()
+    }
 
+    #[test]
+    fn each_stage_fails_with_its_own_code_and_status() {
+        let text = general_purpose::STANDARD.encode(b"plain text");
+        let truncated_png = general_purpose::STANDARD.encode(b"\x89PNG\r\n\x1a\n truncated");
+        let oversized = "A".repeat(MAX_UPLOAD_BYTES / 3 * 4 + 8);
+        let unknown_algorithm = br#"{"alg_type": "no-such-algorithm", "image": ""}"#;
+        let cases = [
+            (b"{\"alg_type\": ".to_vec(), "invalid_json", StatusCode::BAD_REQUEST),
+            (request("not base64!"), "invalid_base64", StatusCode::BAD_REQUEST),
+            (request(&oversized), "payload_too_large", StatusCode::PAYLOAD_TOO_LARGE),
+            (request(&text), "unsupported_format", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            (request(&truncated_png), "undecodable_image", StatusCode::UNPROCESSABLE_ENTITY),
+            (unknown_algorithm.to_vec(), "validation_failed", StatusCode::UNPROCESSABLE_ENTITY),
+            (request(""), "validation_failed", StatusCode::UNPROCESSABLE_ENTITY),
+        ];
+        for (body, code, status) in cases {
+            let e = failure(&body);
+            assert_eq!((e.code(), e.status()), (code, status), "{}", e);
+        }
+        for e in [PipelineError::Processing(String::new()), PipelineError::Encode(String::new())] {
+            assert_eq!(e.status(), StatusCode::INTERNAL_SERVER_ERROR, "{}", e);
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_named() {
+        for (body, missing) in [(r#"{"image": "x"}"#, "alg_type"), (r#"{"alg_type": "atkinson"}"#, "image")] {
+            let Err(PipelineError::Validation { field, detail }) = parse_input(body.as_bytes()) else {
+                panic!("{} was not a validation failure", body);
+            };
+            assert_eq!(field.as_deref(), Some(missing));
+            assert_eq!(detail, format!("missing field `{}`", missing));
+        }
+    }
+
+    #[test]
+    fn details_leave_out_the_json_position() {
+        let bodies = [r#"{"alg_type": "atkinson", "image": 4}"#, r#"{"alg_type": "atkinson", "image": "#, "[1, 2"];
+        for body in bodies {
+            let e = parse_input(body.as_bytes()).err().expect("rejected");
+            assert!(!e.detail().contains(" at line "), "{}", e);
+        }
+        let Err(PipelineError::Validation { field, .. }) =
+            parse_input(br#"{"alg_type": "atkinson", "image": "x", "params": {"threshold": 999}}"#)
+        else {
+            panic!("out-of-range threshold was not a validation failure");
+        };
+        assert_eq!(field.as_deref(), Some("params.threshold"));
+    }
+}
@@ -0,0 +1,1000 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::blue_noise::BLUE_NOISE_SIZES;
+use crate::cmyk::{CmykHalftone, CmykOutput, ColorMode};
+use crate::diffusion::{kernels, DiffusionOptions, ErrorDiffusion, Kernel, KernelSpec, MAX_KERNEL_SIZE};
+use crate::grayscale::{Grayscale, GrayscaleSpec};
+use crate::halftone::{DotShape, Halftone};
+use crate::levels::{Levels, LevelsSpec, MAX_LEVELS};
+use crate::metric::ColorMetric;
+use crate::ordered::{Ordered, ThresholdMapSpec, BAYER_SIZES, MAX_MAP_SIZE};
+use crate::palette::{PaletteSpec, MAX_PALETTE_SIZE};
+use crate::pipeline::{PaletteSource, Pipeline};
+use crate::preprocess::{Equalize, EqualizeMode, Preprocess, Sharpen, SharpenMode, ToneCurveSpec, MAX_CURVE_POINTS};
+use crate::quantize::Quantizer;
+use crate::threshold::Threshold;
+use crate::{Ditherer, PaletteDitherer};
+
+/// Every dithering algorithm the crate can run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    FloydSteinberg,
+    Ordered,
+    Atkinson,
+    Threshold,
+    JarvisJudiceNinke,
+    Stucki,
+    Burkes,
+    Sierra,
+    TwoRowSierra,
+    SierraLite,
+    Fan,
+    Custom,
+    BlueNoise,
+    ThresholdMap,
+    Halftone,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 15] = [
+        Algorithm::FloydSteinberg,
+        Algorithm::Ordered,
+        Algorithm::Atkinson,
+        Algorithm::Threshold,
+        Algorithm::JarvisJudiceNinke,
+        Algorithm::Stucki,
+        Algorithm::Burkes,
+        Algorithm::Sierra,
+        Algorithm::TwoRowSierra,
+        Algorithm::SierraLite,
+        Algorithm::Fan,
+        Algorithm::Custom,
+        Algorithm::BlueNoise,
+        Algorithm::ThresholdMap,
+        Algorithm::Halftone,
+    ];
+
+    /// Canonical name used in requests and responses.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::FloydSteinberg => "floyd-steinberg",
+            Algorithm::Ordered => "ordered",
+            Algorithm::Atkinson => "atkinson",
+            Algorithm::Threshold => "threshold",
+            Algorithm::JarvisJudiceNinke => "jarvis-judice-ninke",
+            Algorithm::Stucki => "stucki",
+            Algorithm::Burkes => "burkes",
+            Algorithm::Sierra => "sierra",
+            Algorithm::TwoRowSierra => "two-row-sierra",
+            Algorithm::SierraLite => "sierra-lite",
+            Algorithm::Fan => "fan",
+            Algorithm::Custom => "custom",
+            Algorithm::BlueNoise => "blue-noise",
+            Algorithm::ThresholdMap => "threshold-map",
+            Algorithm::Halftone => "halftone",
+        }
+    }
+
+    /// Other spellings accepted for the same algorithm.
+    pub fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Algorithm::FloydSteinberg => &["floyd_steinberg", "floydsteinberg", "fs"],
+            Algorithm::Ordered => &["bayer", "ordered-dither"],
+            Algorithm::Atkinson => &[],
+            Algorithm::Threshold => &["none"],
+            Algorithm::JarvisJudiceNinke => &["jjn", "jarvis", "jarvis_judice_ninke"],
+            Algorithm::Stucki => &[],
+            Algorithm::Burkes => &[],
+            Algorithm::Sierra => &["sierra3", "sierra-3"],
+            Algorithm::TwoRowSierra => &["sierra2", "sierra-2", "two_row_sierra"],
+            Algorithm::SierraLite => &["sierra-2-4a", "sierra_lite"],
+            Algorithm::Fan => &[],
+            Algorithm::Custom => &["custom-kernel"],
+            Algorithm::BlueNoise => &["blue_noise", "void-and-cluster"],
+            Algorithm::ThresholdMap => &["threshold_map", "custom-map", "pattern"],
+            Algorithm::Halftone => &["am", "am-screen", "clustered-dot"],
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Algorithm::FloydSteinberg => "Error diffusion to four neighbours; the classic general-purpose dither",
+            Algorithm::Ordered => "Bayer matrix thresholding; fast, regular cross-hatch texture",
+            Algorithm::Atkinson => "Error diffusion passing on 3/4 of the error; crisp, high-contrast look",
+            Algorithm::Threshold => "Plain cut at a single gray level, no dithering",
+            Algorithm::JarvisJudiceNinke => "Error diffusion over three rows; smooth gradients, slower",
+            Algorithm::Stucki => "Three-row diffusion like Jarvis-Judice-Ninke with sharper weights",
+            Algorithm::Burkes => "Two-row simplification of Stucki",
+            Algorithm::Sierra => "Three-row diffusion close to Jarvis-Judice-Ninke, a little faster",
+            Algorithm::TwoRowSierra => "Two-row Sierra variant",
+            Algorithm::SierraLite => "Minimal three-neighbour Sierra kernel; nearly as good as Floyd-Steinberg",
+            Algorithm::Fan => "Floyd-Steinberg variant that spreads error further left",
+            Algorithm::Custom => "Error diffusion with a caller-supplied kernel",
+            Algorithm::BlueNoise => "Ordered dithering with a void-and-cluster blue-noise map; unstructured texture",
+            Algorithm::ThresholdMap => "Ordered dithering with a caller-supplied threshold map, for patterned screens",
+            Algorithm::Halftone => "Clustered-dot AM screen with ruling, angle and dot shape, as for offset and screen print",
+        }
+    }
+
+    /// The built-in error-diffusion kernel behind this algorithm, if it has one.
+    pub fn kernel(self) -> Option<Kernel> {
+        match self {
+            Algorithm::FloydSteinberg => Some(kernels::FLOYD_STEINBERG),
+            Algorithm::Atkinson => Some(kernels::ATKINSON),
+            Algorithm::JarvisJudiceNinke => Some(kernels::JARVIS_JUDICE_NINKE),
+            Algorithm::Stucki => Some(kernels::STUCKI),
+            Algorithm::Burkes => Some(kernels::BURKES),
+            Algorithm::Sierra => Some(kernels::SIERRA),
+            Algorithm::TwoRowSierra => Some(kernels::TWO_ROW_SIERRA),
+            Algorithm::SierraLite => Some(kernels::SIERRA_LITE),
+            Algorithm::Fan => Some(kernels::FAN),
+            Algorithm::Ordered
+            | Algorithm::Threshold
+            | Algorithm::Custom
+            | Algorithm::BlueNoise
+            | Algorithm::ThresholdMap
+            | Algorithm::Halftone => None,
+        }
+    }
+
+    /// Whether this algorithm's error diffusion scans serpentine when `serpentine` is not given.
+    pub const fn serpentine_default(self) -> bool {
+        // These two scanned left-to-right before serpentine existed, so they still do unless asked
+        !matches!(self, Algorithm::FloydSteinberg | Algorithm::Atkinson)
+    }
+
+    /// The `params` fields this algorithm understands.
+    pub fn params(self) -> Vec<ParamSpec> {
+        let serpentine = ParamSpec { kind: ParamKind::Boolean { default: self.serpentine_default() }, ..SERPENTINE };
+        let own: &[ParamSpec] = match self {
+            Algorithm::Ordered => &[MATRIX_SIZE, OFFSET, ROTATION],
+            Algorithm::Threshold => &[THRESHOLD],
+            Algorithm::BlueNoise => &[BLUE_NOISE_SIZE, SEED, OFFSET, ROTATION],
+            Algorithm::ThresholdMap => &[THRESHOLD_MAP, OFFSET, ROTATION],
+            Algorithm::Halftone => &[LPI, DPI, ANGLE, DOT_SHAPE],
+            Algorithm::Custom => &[KERNEL, THRESHOLD, STRENGTH, serpentine, EDGE_BOOST],
+            _ => &[THRESHOLD, STRENGTH, serpentine, EDGE_BOOST],
+        };
+        let modes = self.output_modes();
+        let group = |mode, specs| if modes.contains(&mode) { specs } else { &[][..] };
+        [
+            own,
+            &[OUTPUT_LEVELS],
+            group(OutputMode::Cmyk, CMYK_PARAMS),
+            group(OutputMode::Palette, PALETTE_PARAMS),
+            &[LINEAR_LIGHT, GRAYSCALE],
+            TONAL_ADJUSTMENTS,
+        ]
+        .concat()
+    }
+
+    pub fn output_modes(self) -> &'static [OutputMode] {
+        match self {
+            Algorithm::Halftone => &[OutputMode::OneBit, OutputMode::MultiLevel, OutputMode::Cmyk],
+            Algorithm::Threshold => &[OutputMode::OneBit, OutputMode::MultiLevel],
+            _ => &[OutputMode::OneBit, OutputMode::MultiLevel, OutputMode::Palette],
+        }
+    }
+
+    /// Validate `params` for this algorithm and build the whole pipeline, colour modes included.
+    pub fn pipeline(self, params: &Params) -> Result<Pipeline, ParamError> {
+        if self == Algorithm::Halftone && params.color_mode == Some(ColorMode::Cmyk) {
+            params.check(self)?;
+            return Ok(Pipeline::cmyk(params.cmyk(), params.cmyk_output.unwrap_or_default()));
+        }
+        let source = match (&params.palette, params.quantizer) {
+            (Some(spec), _) => Some(PaletteSource::Fixed(
+                spec.build().map_err(|e| ParamError::new("palette", e.to_string()))?,
+            )),
+            (None, Some(quantizer)) => Some(PaletteSource::Extracted {
+                quantizer,
+                colors: params.palette_size.unwrap_or(16) as usize,
+                seed: params.palette_seed.unwrap_or(0),
+            }),
+            (None, None) => None,
+        };
+        if let Some(source) = source {
+            params.check(self)?;
+            let ditherer: Box<dyn PaletteDitherer> = match (self.error_diffusion(params)?, self.screen(params)?) {
+                (Some(diffusion), _) => Box::new(diffusion),
+                (None, Some(screen)) => Box::new(screen),
+                (None, None) => return Err(ParamError::new("palette", format!("`{}` does not take `palette`", self))),
+            };
+            return Ok(Pipeline::paletted(ditherer, source, params.color_metric.unwrap_or_default()));
+        }
+        Ok(Pipeline::gray(self.ditherer(params)?, params.grayscale(), params.preprocess()))
+    }
+
+    /// Validate `params` for this algorithm and build the matching ditherer.
+    pub fn ditherer(self, params: &Params) -> Result<Box<dyn Ditherer>, ParamError> {
+        params.check(self)?;
+        if let Some(diffusion) = self.error_diffusion(params)? {
+            return Ok(Box::new(diffusion));
+        }
+        if let Some(screen) = self.screen(params)? {
+            return Ok(Box::new(screen));
+        }
+        Ok(match self {
+            Algorithm::Halftone => Box::new(params.halftone()),
+            _ => Box::new(Threshold {
+                level: params.threshold.unwrap_or(128),
+                levels: params.levels(),
+            }),
+        })
+    }
+
+    // The error-diffusion engine behind this algorithm, if it is one
+    fn error_diffusion(self, params: &Params) -> Result<Option<ErrorDiffusion>, ParamError> {
+        let kernel = match (self, &params.kernel) {
+            (Algorithm::Custom, Some(spec)) => Some(spec.build().map_err(|e| ParamError::new("kernel", e.to_string()))?),
+            _ => self.kernel(),
+        };
+        Ok(kernel.map(|kernel| ErrorDiffusion { kernel, options: params.diffusion(self) }))
+    }
+
+    // The threshold-map screen behind this algorithm, if it is an ordered one
+    fn screen(self, params: &Params) -> Result<Option<Ordered>, ParamError> {
+        let screen = match (self, &params.threshold_map) {
+            (Algorithm::Ordered, _) => Ordered::bayer(params.matrix_size.unwrap_or(4)),
+            (Algorithm::BlueNoise, _) => Ordered::blue_noise(params.matrix_size.unwrap_or(64), params.seed.unwrap_or(0)),
+            (Algorithm::ThresholdMap, Some(spec)) => {
+                Ordered::new(spec.build().map_err(|e| ParamError::new("threshold_map", e.to_string()))?)
+            }
+            _ => return Ok(None),
+        };
+        let [dx, dy] = params.offset.unwrap_or([0, 0]);
+        Ok(Some(Ordered {
+            offset: (dx, dy),
+            rotation: params.rotation.unwrap_or(0.0),
+            levels: params.levels(),
+            ..screen
+        }))
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = UnknownAlgorithm;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.trim().to_ascii_lowercase();
+        Algorithm::ALL
+            .into_iter()
+            .find(|alg| alg.name() == wanted || alg.aliases().contains(&wanted.as_str()))
+            .ok_or_else(|| UnknownAlgorithm::new(s))
+    }
+}
+
+impl<'de> Deserialize<'de> for Algorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Algorithm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// An algorithm name that matches nothing, with the closest valid name if there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownAlgorithm {
+    pub given: String,
+    pub suggestion: Option<Algorithm>,
+}
+
+impl UnknownAlgorithm {
+    fn new(given: &str) -> Self {
+        let wanted = given.trim().to_ascii_lowercase();
+        let wanted = wanted.as_str();
+        let suggestion = Algorithm::ALL
+            .into_iter()
+            .flat_map(|alg| {
+                std::iter::once(alg.name())
+                    .chain(alg.aliases().iter().copied())
+                    .map(move |name| (edit_distance(wanted, name), alg))
+            })
+            .min_by_key(|&(distance, _)| distance)
+            .filter(|&(distance, _)| distance <= (wanted.len() / 3).max(2))
+            .map(|(_, alg)| alg);
+        UnknownAlgorithm {
+            given: given.to_string(),
+            suggestion,
+        }
+    }
+}
+
+impl fmt::Display for UnknownAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let valid: Vec<&str> = Algorithm::ALL.iter().map(|alg| alg.name()).collect();
+        write!(f, "unknown algorithm `{}`, expected one of: {}", self.given, valid.join(", "))?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnknownAlgorithm {}
+
+// Levenshtein distance, used to suggest the algorithm a typo was aiming for
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == cb { prev } else { 1 + prev.min(cur).min(row[j]) };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Optional tuning knobs; which ones apply depends on the algorithm.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Params {
+    pub threshold: Option<u8>,
+    pub matrix_size: Option<u32>,
+    pub strength: Option<f32>,
+    pub serpentine: Option<bool>,
+    pub seed: Option<u64>,
+    pub output_levels: Option<LevelsSpec>,
+    pub kernel: Option<KernelSpec>,
+    pub threshold_map: Option<ThresholdMapSpec>,
+    pub offset: Option<[i32; 2]>,
+    pub rotation: Option<f32>,
+    pub lpi: Option<f32>,
+    pub dpi: Option<f32>,
+    pub angle: Option<f32>,
+    pub dot_shape: Option<DotShape>,
+    pub color_mode: Option<ColorMode>,
+    pub cmyk_output: Option<CmykOutput>,
+    pub gcr: Option<f32>,
+    pub ucr: Option<f32>,
+    pub palette: Option<PaletteSpec>,
+    pub quantizer: Option<Quantizer>,
+    pub palette_size: Option<u16>,
+    pub palette_seed: Option<u64>,
+    pub color_metric: Option<ColorMetric>,
+    pub linear_light: Option<bool>,
+    pub grayscale: Option<GrayscaleSpec>,
+    pub equalize: Option<EqualizeMode>,
+    pub clahe_tile_size: Option<u32>,
+    pub clahe_clip_limit: Option<f32>,
+    pub black_point: Option<u8>,
+    pub white_point: Option<u8>,
+    pub output_black: Option<u8>,
+    pub output_white: Option<u8>,
+    pub gamma: Option<f32>,
+    pub brightness: Option<f32>,
+    pub contrast: Option<f32>,
+    pub tone_curve: Option<ToneCurveSpec>,
+    pub sharpen: Option<SharpenMode>,
+    pub sharpen_radius: Option<f32>,
+    pub sharpen_amount: Option<f32>,
+    pub sharpen_threshold: Option<u8>,
+    pub edge_boost: Option<f32>,
+}
+
+impl Params {
+    /// Reject parameters the algorithm does not use and values outside their range.
+    pub fn validate(&self, alg: Algorithm) -> Result<(), ParamError> {
+        self.check(alg)?;
+        if let Some(spec) = &self.threshold_map {
+            spec.build().map_err(|e| ParamError::new("threshold_map", e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    // Everything validate does short of building the threshold map, which can mean decoding an
+    // image; the ditherer builds it once itself and reports the same error
+    fn check(&self, alg: Algorithm) -> Result<(), ParamError> {
+        let specs = alg.params();
+        for (name, value) in self.given() {
+            let Some(value) = value else { continue };
+            let spec = specs
+                .iter()
+                .find(|spec| spec.name == name)
+                .ok_or_else(|| ParamError::new(name, format!("`{}` does not take `{}`", alg, name)))?;
+            spec.check(value).map_err(|detail| ParamError::new(name, detail))?;
+        }
+
+        match &self.kernel {
+            Some(spec) => {
+                spec.build().map_err(|e| ParamError::new("kernel", e.to_string()))?;
+            }
+            None if alg == Algorithm::Custom => {
+                return Err(ParamError::new("kernel", format!("`{}` needs a kernel", alg)));
+            }
+            None => {}
+        }
+        if self.threshold_map.is_none() && alg == Algorithm::ThresholdMap {
+            return Err(ParamError::new("threshold_map", format!("`{}` needs a threshold map", alg)));
+        }
+        if alg == Algorithm::Halftone {
+            let screen = self.halftone();
+            if screen.dpi / screen.lpi < 2.0 {
+                return Err(ParamError::new("lpi", format!("must be at most half the dpi ({})", screen.dpi)));
+            }
+        }
+        if self.color_mode == Some(ColorMode::Cmyk) {
+            if self.angle.is_some() {
+                return Err(ParamError::new("angle", "CMYK separations use their own screen angles".to_string()));
+            }
+            if self.linear_light.is_some() {
+                return Err(ParamError::new("linear_light", "CMYK plates are ink coverage, not light".to_string()));
+            }
+            if self.output_levels.is_some() {
+                return Err(ParamError::new("output_levels", "CMYK plates are one bit per ink".to_string()));
+            }
+            if self.grayscale.is_some() {
+                return Err(ParamError::new("grayscale", "CMYK separates colour rather than converting to gray".to_string()));
+            }
+            if let Some(name) = self.preprocessing() {
+                return Err(ParamError::new(name, "only applies to gray output".to_string()));
+            }
+        } else {
+            let cmyk_only = [
+                ("cmyk_output", self.cmyk_output.is_some()),
+                ("gcr", self.gcr.is_some()),
+                ("ucr", self.ucr.is_some()),
+            ];
+            if let Some((name, _)) = cmyk_only.into_iter().find(|&(_, given)| given) {
+                return Err(ParamError::new(name, "only applies with `color_mode` cmyk".to_string()));
+            }
+        }
+        if let Some(spec) = &self.grayscale {
+            spec.build().map_err(|e| ParamError::new("grayscale", e.to_string()))?;
+        }
+        if self.equalize != Some(EqualizeMode::Clahe) {
+            let clahe_only = [
+                ("clahe_tile_size", self.clahe_tile_size.is_some()),
+                ("clahe_clip_limit", self.clahe_clip_limit.is_some()),
+            ];
+            if let Some((name, _)) = clahe_only.into_iter().find(|&(_, given)| given) {
+                return Err(ParamError::new(name, "only applies with `equalize` clahe".to_string()));
+            }
+        }
+        match self.sharpen {
+            Some(SharpenMode::Unsharp) => {}
+            Some(SharpenMode::Laplacian) => {
+                let unsharp_only = [
+                    ("sharpen_radius", self.sharpen_radius.is_some()),
+                    ("sharpen_threshold", self.sharpen_threshold.is_some()),
+                ];
+                if let Some((name, _)) = unsharp_only.into_iter().find(|&(_, given)| given) {
+                    return Err(ParamError::new(name, "only applies with `sharpen` unsharp".to_string()));
+                }
+            }
+            None => {
+                let sharpen_only = [
+                    ("sharpen_radius", self.sharpen_radius.is_some()),
+                    ("sharpen_amount", self.sharpen_amount.is_some()),
+                    ("sharpen_threshold", self.sharpen_threshold.is_some()),
+                ];
+                if let Some((name, _)) = sharpen_only.into_iter().find(|&(_, given)| given) {
+                    return Err(ParamError::new(name, "only applies with `sharpen`".to_string()));
+                }
+            }
+        }
+        if let Some(spec) = &self.tone_curve {
+            spec.build().map_err(|e| ParamError::new("tone_curve", e.to_string()))?;
+        }
+        let adjust = self.preprocess();
+        if adjust.black_point >= adjust.white_point {
+            let name = if self.black_point.is_some() { "black_point" } else { "white_point" };
+            return Err(ParamError::new(name, "`black_point` must be below `white_point`".to_string()));
+        }
+        if let Some(spec) = &self.palette {
+            spec.build().map_err(|e| ParamError::new("palette", e.to_string()))?;
+        }
+        match self.quantizer {
+            Some(_) if self.palette.is_some() => {
+                return Err(ParamError::new("quantizer", "give either `palette` or `quantizer`, not both".to_string()));
+            }
+            Some(quantizer) if quantizer != Quantizer::KMeans && self.palette_seed.is_some() => {
+                return Err(ParamError::new("palette_seed", "only applies to k-means".to_string()));
+            }
+            Some(_) => {}
+            None => {
+                let extract_only = [
+                    ("palette_size", self.palette_size.is_some()),
+                    ("palette_seed", self.palette_seed.is_some()),
+                ];
+                if let Some((name, _)) = extract_only.into_iter().find(|&(_, given)| given) {
+                    return Err(ParamError::new(name, "only applies with `quantizer`".to_string()));
+                }
+            }
+        }
+        if self.palette.is_some() || self.quantizer.is_some() {
+            let gray_only = [
+                ("output_levels", self.output_levels.is_some()),
+                ("threshold", self.threshold.is_some()),
+                ("grayscale", self.grayscale.is_some()),
+                ("edge_boost", self.edge_boost.is_some()),
+            ];
+            let given = gray_only.into_iter().find(|&(_, given)| given).map(|(name, _)| name);
+            if let Some(name) = given.or_else(|| self.preprocessing()) {
+                return Err(ParamError::new(name, "does not apply to palette output".to_string()));
+            }
+        } else if self.color_metric.is_some() {
+            return Err(ParamError::new("color_metric", "only applies to palette output".to_string()));
+        }
+        if let Some(spec) = &self.output_levels {
+            let levels = spec.build().map_err(|e| ParamError::new("output_levels", e.to_string()))?;
+            if levels.len() > 2 && self.threshold.is_some() {
+                return Err(ParamError::new("threshold", "only applies to two-level output".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // Every field by name, with its value as a number where one is given; check looks each up in the specs
+    fn given(&self) -> Vec<(&'static str, Option<f64>)> {
+        vec![
+            ("threshold", self.threshold.map(f64::from)),
+            ("matrix_size", self.matrix_size.map(f64::from)),
+            ("strength", self.strength.map(f64::from)),
+            ("serpentine", self.serpentine.map(|b| b as u8 as f64)),
+            ("seed", self.seed.map(|v| v as f64)),
+            ("output_levels", self.output_levels.as_ref().map(|spec| spec.count() as f64)),
+            ("kernel", self.kernel.as_ref().map(|_| 0.0)),
+            ("threshold_map", self.threshold_map.as_ref().map(|_| 0.0)),
+            ("offset", self.offset.map(|[x, y]| x.unsigned_abs().max(y.unsigned_abs()) as f64)),
+            ("rotation", self.rotation.map(f64::from)),
+            ("lpi", self.lpi.map(f64::from)),
+            ("dpi", self.dpi.map(f64::from)),
+            ("angle", self.angle.map(f64::from)),
+            ("dot_shape", self.dot_shape.map(|_| 0.0)),
+            ("color_mode", self.color_mode.map(|_| 0.0)),
+            ("cmyk_output", self.cmyk_output.map(|_| 0.0)),
+            ("gcr", self.gcr.map(f64::from)),
+            ("ucr", self.ucr.map(f64::from)),
+            ("palette", self.palette.as_ref().map(|_| 0.0)),
+            ("quantizer", self.quantizer.map(|_| 0.0)),
+            ("palette_size", self.palette_size.map(f64::from)),
+            ("palette_seed", self.palette_seed.map(|v| v as f64)),
+            ("color_metric", self.color_metric.map(|_| 0.0)),
+            ("linear_light", self.linear_light.map(|b| b as u8 as f64)),
+            ("grayscale", self.grayscale.as_ref().map(|_| 0.0)),
+            ("equalize", self.equalize.map(|_| 0.0)),
+            ("clahe_tile_size", self.clahe_tile_size.map(f64::from)),
+            ("clahe_clip_limit", self.clahe_clip_limit.map(f64::from)),
+            ("black_point", self.black_point.map(f64::from)),
+            ("white_point", self.white_point.map(f64::from)),
+            ("output_black", self.output_black.map(f64::from)),
+            ("output_white", self.output_white.map(f64::from)),
+            ("gamma", self.gamma.map(f64::from)),
+            ("brightness", self.brightness.map(f64::from)),
+            ("contrast", self.contrast.map(f64::from)),
+            ("tone_curve", self.tone_curve.as_ref().map(|spec| spec.0.len() as f64)),
+            ("sharpen", self.sharpen.map(|_| 0.0)),
+            ("sharpen_radius", self.sharpen_radius.map(f64::from)),
+            ("sharpen_amount", self.sharpen_amount.map(f64::from)),
+            ("sharpen_threshold", self.sharpen_threshold.map(f64::from)),
+            ("edge_boost", self.edge_boost.map(f64::from)),
+        ]
+    }
+
+    fn halftone(&self) -> Halftone {
+        let defaults = Halftone::default();
+        Halftone {
+            lpi: self.lpi.unwrap_or(defaults.lpi),
+            dpi: self.dpi.unwrap_or(defaults.dpi),
+            angle: self.angle.unwrap_or(defaults.angle),
+            shape: self.dot_shape.unwrap_or(defaults.shape),
+            levels: self.levels(),
+        }
+    }
+
+    fn cmyk(&self) -> CmykHalftone {
+        let defaults = CmykHalftone::default();
+        let gcr = self.gcr.unwrap_or(defaults.gcr);
+        CmykHalftone {
+            screen: Halftone { levels: Levels::default(), ..self.halftone() },
+            gcr,
+            ucr: self.ucr.unwrap_or(gcr),
+        }
+    }
+
+    fn diffusion(&self, alg: Algorithm) -> DiffusionOptions {
+        DiffusionOptions {
+            threshold: self.threshold.unwrap_or(128),
+            strength: self.strength.unwrap_or(1.0),
+            serpentine: self.serpentine.unwrap_or(alg.serpentine_default()),
+            levels: self.levels(),
+            edge_boost: self.edge_boost.unwrap_or(0.0),
+        }
+    }
+
+    // Validated already, so a bad spec cannot reach here
+    fn levels(&self) -> Levels {
+        self.output_levels
+            .as_ref()
+            .and_then(|spec| spec.build().ok())
+            .unwrap_or_default()
+            .linear_light(self.linear_light())
+    }
+
+    // The first tonal adjustment given, if any; they only run on gray output
+    fn preprocessing(&self) -> Option<&'static str> {
+        let given = self.given();
+        TONAL_ADJUSTMENTS
+            .iter()
+            .map(|spec| spec.name)
+            .find(|&name| given.iter().any(|&(field, value)| field == name && value.is_some()))
+    }
+
+    fn preprocess(&self) -> Preprocess {
+        let defaults = Preprocess::default();
+        let equalize = self.equalize.map(|mode| match mode {
+            EqualizeMode::Global => Equalize::Global,
+            EqualizeMode::Clahe => Equalize::Clahe {
+                tile_size: self.clahe_tile_size.unwrap_or(64),
+                clip_limit: self.clahe_clip_limit.unwrap_or(2.0),
+            },
+        });
+        let sharpen = self.sharpen.map(|mode| match mode {
+            SharpenMode::Unsharp => Sharpen::Unsharp {
+                radius: self.sharpen_radius.unwrap_or(1.0),
+                amount: self.sharpen_amount.unwrap_or(1.0),
+                threshold: self.sharpen_threshold.unwrap_or(0),
+            },
+            SharpenMode::Laplacian => Sharpen::Laplacian { amount: self.sharpen_amount.unwrap_or(0.5) },
+        });
+        Preprocess {
+            equalize,
+            black_point: self.black_point.unwrap_or(defaults.black_point),
+            white_point: self.white_point.unwrap_or(defaults.white_point),
+            output_black: self.output_black.unwrap_or(defaults.output_black),
+            output_white: self.output_white.unwrap_or(defaults.output_white),
+            gamma: self.gamma.unwrap_or(defaults.gamma),
+            brightness: self.brightness.unwrap_or(defaults.brightness),
+            contrast: self.contrast.unwrap_or(defaults.contrast),
+            curve: self.tone_curve.as_ref().and_then(|spec| spec.build().ok()),
+            sharpen,
+        }
+    }
+
+    fn grayscale(&self) -> Grayscale {
+        self.grayscale
+            .as_ref()
+            .and_then(|spec| spec.build().ok())
+            .unwrap_or_default()
+    }
+
+    fn linear_light(&self) -> bool {
+        self.linear_light.unwrap_or(true)
+    }
+}
+
+/// A `params` field that is not accepted, with the reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamError {
+    pub param: &'static str,
+    pub detail: String,
+}
+
+impl ParamError {
+    fn new(param: &'static str, detail: String) -> Self {
+        ParamError { param, detail }
+    }
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.param, self.detail)
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+/// Type, range and default of one `params` field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: ParamKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamKind {
+    Integer { min: u64, max: u64, default: u64 },
+    Number { min: f64, max: f64, default: f64 },
+    Boolean { default: bool },
+    Choice { values: &'static [u32], default: u32 },
+    Kernel { max_size: usize },
+    ThresholdMap { max_size: u32 },
+    /// An `[x, y]` pair, each between `-max` and `max`.
+    Offset { max: u32 },
+    Name { values: &'static [&'static str], default: &'static str },
+    /// A level count from 2 to `max`, or a list of up to `max` gray values.
+    Levels { max: usize },
+    /// A built-in palette name, or a list of 2 to `max_size` hex colours.
+    Palette { max_size: usize },
+    /// One of `values`, or `[red, green, blue]` channel weights.
+    Grayscale { values: &'static [&'static str], default: &'static str },
+    /// `[input, output]` control points, 2 to `max_points` of them.
+    Curve { max_points: usize },
+}
+
+impl ParamSpec {
+    fn check(&self, value: f64) -> Result<(), String> {
+        match self.kind {
+            ParamKind::Integer { min, max, .. } if value < min as f64 || value > max as f64 => {
+                Err(format!("must be between {} and {}", min, max))
+            }
+            ParamKind::Number { min, max, .. } if !(min..=max).contains(&value) => {
+                Err(format!("must be between {:?} and {:?}", min, max))
+            }
+            ParamKind::Choice { values, .. } if !values.iter().any(|&v| v as f64 == value) => {
+                Err(format!("must be one of {:?}", values))
+            }
+            ParamKind::Curve { max_points } if value < 2.0 || value > max_points as f64 => {
+                Err(format!("must have between 2 and {} control points", max_points))
+            }
+            ParamKind::Levels { max } if value < 2.0 || value > max as f64 => {
+                Err(format!("must be between 2 and {} levels", max))
+            }
+            ParamKind::Offset { max } if value > max as f64 => {
+                Err(format!("components must be between -{} and {}", max, max))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Groups shared by every algorithm with the matching output mode, or by all of them
+const CMYK_PARAMS: &[ParamSpec] = &[COLOR_MODE, CMYK_OUTPUT, GCR, UCR];
+const PALETTE_PARAMS: &[ParamSpec] = &[PALETTE, QUANTIZER, PALETTE_SIZE, PALETTE_SEED, COLOR_METRIC];
+const TONAL_ADJUSTMENTS: &[ParamSpec] = &[
+    EQUALIZE, CLAHE_TILE_SIZE, CLAHE_CLIP_LIMIT, SHARPEN, SHARPEN_RADIUS, SHARPEN_AMOUNT, SHARPEN_THRESHOLD,
+    BLACK_POINT, WHITE_POINT, OUTPUT_BLACK, OUTPUT_WHITE, GAMMA, BRIGHTNESS, CONTRAST, TONE_CURVE,
+];
+
+const THRESHOLD: ParamSpec = ParamSpec {
+    name: "threshold",
+    description: "Gray level at or above which a pixel turns white",
+    kind: ParamKind::Integer { min: 0, max: 255, default: 128 },
+};
+const MATRIX_SIZE: ParamSpec = ParamSpec {
+    name: "matrix_size",
+    description: "Side length of the Bayer threshold matrix; larger gives more tone levels",
+    kind: ParamKind::Choice { values: &BAYER_SIZES, default: 4 },
+};
+const BLUE_NOISE_SIZE: ParamSpec = ParamSpec {
+    name: "matrix_size",
+    description: "Side length of the blue-noise map; larger repeats less visibly",
+    kind: ParamKind::Choice { values: &BLUE_NOISE_SIZES, default: 64 },
+};
+const STRENGTH: ParamSpec = ParamSpec {
+    name: "strength",
+    description: "Fraction of the quantisation error passed on to neighbours",
+    kind: ParamKind::Number { min: 0.0, max: 1.0, default: 1.0 },
+};
+const SERPENTINE: ParamSpec = ParamSpec {
+    name: "serpentine",
+    description: "Scan alternate rows right-to-left with the kernel mirrored; avoids directional worms",
+    kind: ParamKind::Boolean { default: true },
+};
+const EDGE_BOOST: ParamSpec = ParamSpec {
+    name: "edge_boost",
+    description: "Sharpen edges by modulating the threshold with local contrast, leaving the source untouched; 0 is off",
+    kind: ParamKind::Number { min: 0.0, max: 10.0, default: 0.0 },
+};
+const SEED: ParamSpec = ParamSpec {
+    name: "seed",
+    description: "Seed for the noise generator; equal seeds give equal output",
+    kind: ParamKind::Integer { min: 0, max: u64::MAX, default: 0 },
+};
+const KERNEL: ParamSpec = ParamSpec {
+    name: "kernel",
+    description: "Weight matrix with `*` at the current pixel, plus an optional divisor",
+    kind: ParamKind::Kernel { max_size: MAX_KERNEL_SIZE },
+};
+const THRESHOLD_MAP: ParamSpec = ParamSpec {
+    name: "threshold_map",
+    description: "Threshold texture: {\"matrix\": [[levels]]} or {\"image\": base64 grayscale image}",
+    kind: ParamKind::ThresholdMap { max_size: MAX_MAP_SIZE },
+};
+const OFFSET: ParamSpec = ParamSpec {
+    name: "offset",
+    description: "Shift of the tiled map as [x, y], in map cells",
+    kind: ParamKind::Offset { max: 4096 },
+};
+const ROTATION: ParamSpec = ParamSpec {
+    name: "rotation",
+    description: "Rotation of the tiled map in degrees, clockwise",
+    kind: ParamKind::Number { min: -360.0, max: 360.0, default: 0.0 },
+};
+const LPI: ParamSpec = ParamSpec {
+    name: "lpi",
+    description: "Screen ruling in lines per inch",
+    kind: ParamKind::Number { min: 1.0, max: 600.0, default: 60.0 },
+};
+const DPI: ParamSpec = ParamSpec {
+    name: "dpi",
+    description: "Output resolution in dots per inch; one image pixel is one dot",
+    kind: ParamKind::Number { min: 1.0, max: 4800.0, default: 300.0 },
+};
+const ANGLE: ParamSpec = ParamSpec {
+    name: "angle",
+    description: "Screen angle in degrees; not used in CMYK mode",
+    kind: ParamKind::Number { min: -360.0, max: 360.0, default: 45.0 },
+};
+const DOT_SHAPE: ParamSpec = ParamSpec {
+    name: "dot_shape",
+    description: "Shape of the halftone dot",
+    kind: ParamKind::Name { values: &["round", "ellipse", "square", "line", "diamond"], default: "round" },
+};
+const COLOR_MODE: ParamSpec = ParamSpec {
+    name: "color_mode",
+    description: "`gray` screens luma; `cmyk` separates into four inks, each at its classic screen angle",
+    kind: ParamKind::Name { values: &["gray", "cmyk"], default: "gray" },
+};
+const CMYK_OUTPUT: ParamSpec = ParamSpec {
+    name: "cmyk_output",
+    description: "`composite` returns an overprinted preview; `separations` returns one plate per ink",
+    kind: ParamKind::Name { values: &["composite", "separations"], default: "composite" },
+};
+const GCR: ParamSpec = ParamSpec {
+    name: "gcr",
+    description: "Gray component replacement: fraction of the neutral component printed in black",
+    kind: ParamKind::Number { min: 0.0, max: 1.0, default: 0.5 },
+};
+const UCR: ParamSpec = ParamSpec {
+    name: "ucr",
+    description: "Undercolour removal: fraction of the neutral component taken out of C, M and Y; defaults to gcr",
+    kind: ParamKind::Number { min: 0.0, max: 1.0, default: 0.5 },
+};
+const OUTPUT_LEVELS: ParamSpec = ParamSpec {
+    name: "output_levels",
+    description: "Output grays: a count of evenly spaced levels, or a list of gray values such as an e-ink panel's",
+    kind: ParamKind::Levels { max: MAX_LEVELS },
+};
+const PALETTE: ParamSpec = ParamSpec {
+    name: "palette",
+    description: "Dither to these colours instead of gray: a built-in palette name or a list of hex colours",
+    kind: ParamKind::Palette { max_size: MAX_PALETTE_SIZE },
+};
+const QUANTIZER: ParamSpec = ParamSpec {
+    name: "quantizer",
+    description: "Dither to a palette extracted from the image itself, by median cut, k-means or octree",
+    kind: ParamKind::Name { values: &["median-cut", "k-means", "octree"], default: "median-cut" },
+};
+const PALETTE_SIZE: ParamSpec = ParamSpec {
+    name: "palette_size",
+    description: "Number of colours to extract; the image may have fewer",
+    kind: ParamKind::Integer { min: 2, max: MAX_PALETTE_SIZE as u64, default: 16 },
+};
+const PALETTE_SEED: ParamSpec = ParamSpec {
+    name: "palette_seed",
+    description: "Seed for k-means starting centres; equal seeds give equal palettes",
+    kind: ParamKind::Integer { min: 0, max: u64::MAX, default: 0 },
+};
+const COLOR_METRIC: ParamSpec = ParamSpec {
+    name: "color_metric",
+    description: "Colour difference used to pick palette entries: rgb, luma-rgb, cie76, ciede2000 or oklab",
+    kind: ParamKind::Name { values: &["rgb", "luma-rgb", "cie76", "ciede2000", "oklab"], default: "rgb" },
+};
+const LINEAR_LIGHT: ParamSpec = ParamSpec {
+    name: "linear_light",
+    description: "Threshold and diffuse error in linear light, so dithered tones keep their perceived brightness",
+    kind: ParamKind::Boolean { default: true },
+};
+const GRAYSCALE: ParamSpec = ParamSpec {
+    name: "grayscale",
+    description: "Colour-to-gray conversion: a luma standard, CIE lightness, a channel, or [r, g, b] mixer weights",
+    kind: ParamKind::Grayscale {
+        values: &["rec601", "rec709", "rec2100", "lightness", "average", "red", "green", "blue", "max", "min"],
+        default: "rec709",
+    },
+};
+const EQUALIZE: ParamSpec = ParamSpec {
+    name: "equalize",
+    description: "Histogram equalisation before any other adjustment: `global`, or `clahe` for adaptive, tile by tile",
+    kind: ParamKind::Name { values: &["global", "clahe"], default: "global" },
+};
+const CLAHE_TILE_SIZE: ParamSpec = ParamSpec {
+    name: "clahe_tile_size",
+    description: "Side of a CLAHE tile in pixels; smaller adapts to more local contrast",
+    kind: ParamKind::Integer { min: 8, max: 1024, default: 64 },
+};
+const CLAHE_CLIP_LIMIT: ParamSpec = ParamSpec {
+    name: "clahe_clip_limit",
+    description: "Most a gray may take of a CLAHE tile, as a multiple of its even share; lower boosts noise less",
+    kind: ParamKind::Number { min: 1.0, max: 64.0, default: 2.0 },
+};
+const SHARPEN: ParamSpec = ParamSpec {
+    name: "sharpen",
+    description: "Sharpen after tonal adjustments: `unsharp` mask, or a `laplacian` for single-pixel edges",
+    kind: ParamKind::Name { values: &["unsharp", "laplacian"], default: "unsharp" },
+};
+const SHARPEN_RADIUS: ParamSpec = ParamSpec {
+    name: "sharpen_radius",
+    description: "Unsharp mask blur radius (Gaussian standard deviation) in pixels",
+    kind: ParamKind::Number { min: 0.1, max: 20.0, default: 1.0 },
+};
+const SHARPEN_AMOUNT: ParamSpec = ParamSpec {
+    name: "sharpen_amount",
+    description: "Strength of sharpening; defaults to 1 for unsharp and 0.5 for laplacian",
+    kind: ParamKind::Number { min: 0.0, max: 5.0, default: 1.0 },
+};
+const SHARPEN_THRESHOLD: ParamSpec = ParamSpec {
+    name: "sharpen_threshold",
+    description: "Smallest difference from the blur, in grays, that unsharp mask sharpens; spares smooth areas",
+    kind: ParamKind::Integer { min: 0, max: 255, default: 0 },
+};
+const BLACK_POINT: ParamSpec = ParamSpec {
+    name: "black_point",
+    description: "Input gray that becomes black; darker grays clip",
+    kind: ParamKind::Integer { min: 0, max: 254, default: 0 },
+};
+const WHITE_POINT: ParamSpec = ParamSpec {
+    name: "white_point",
+    description: "Input gray that becomes white; lighter grays clip",
+    kind: ParamKind::Integer { min: 1, max: 255, default: 255 },
+};
+const OUTPUT_BLACK: ParamSpec = ParamSpec {
+    name: "output_black",
+    description: "Gray that black is raised to after levels",
+    kind: ParamKind::Integer { min: 0, max: 255, default: 0 },
+};
+const OUTPUT_WHITE: ParamSpec = ParamSpec {
+    name: "output_white",
+    description: "Gray that white is lowered to after levels; below `output_black` inverts the image",
+    kind: ParamKind::Integer { min: 0, max: 255, default: 255 },
+};
+const GAMMA: ParamSpec = ParamSpec {
+    name: "gamma",
+    description: "Midtone gamma; above 1 lightens, below 1 darkens",
+    kind: ParamKind::Number { min: 0.1, max: 10.0, default: 1.0 },
+};
+const BRIGHTNESS: ParamSpec = ParamSpec {
+    name: "brightness",
+    description: "Added to every gray, as a fraction of full scale",
+    kind: ParamKind::Number { min: -1.0, max: 1.0, default: 0.0 },
+};
+const CONTRAST: ParamSpec = ParamSpec {
+    name: "contrast",
+    description: "Contrast around mid-gray: -1 flattens to gray, 1 is a hard threshold",
+    kind: ParamKind::Number { min: -1.0, max: 1.0, default: 0.0 },
+};
+const TONE_CURVE: ParamSpec = ParamSpec {
+    name: "tone_curve",
+    description: "Tone curve through [input, output] control points, 0 to 255, with a smooth monotone spline between",
+    kind: ParamKind::Curve { max_points: MAX_CURVE_POINTS },
+};
+
+/// Kinds of output an algorithm can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    OneBit,
+    MultiLevel,
+    Palette,
+    Cmyk,
+}
+
+impl OutputMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputMode::OneBit => "1-bit",
+            OutputMode::MultiLevel => "multi-level",
+            OutputMode::Palette => "palette",
+            OutputMode::Cmyk => "cmyk",
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use dithering::{save_image, to_grayscale, Algorithm, Params};
+use std::env;
+use std::process;
+
+// Dither an image file from the command line:
+//     dither <input> <output> [algorithm]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("usage: {} <input> <output> [algorithm]", args[0]);
+        process::exit(2);
+    }
+
+    let alg: Algorithm = match args.get(3).map(|name| name.parse()) {
+        Some(Ok(alg)) => alg,
+        Some(Err(e)) => fail(e),
+        None => Algorithm::FloydSteinberg,
+    };
+    let ditherer = alg.ditherer(&Params::default()).unwrap_or_else(|e| fail(e));
+
+    let base_img = image::open(&args[1]).unwrap_or_else(|e| fail(e));
+    let gray = to_grayscale(&base_img);
+    save_image(&ditherer.dither(&gray), &args[2]).unwrap_or_else(|e| fail(e));
+}
+
+fn fail(e: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", e);
+    process::exit(1);
+}
@@ -0,0 +1,161 @@
+//! Blue-noise threshold maps from Ulichney's void-and-cluster algorithm.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::ordered::ThresholdMap;
+
+/// Map sizes accepted for blue noise.
+pub const BLUE_NOISE_SIZES: [u32; 4] = [8, 16, 32, 64];
+
+// Spread of the Gaussian used to measure how crowded a neighbourhood is
+const SIGMA: f32 = 1.5;
+
+/// Rank of every cell of a `size` x `size` void-and-cluster pattern, row-major.
+/// Equal sizes and seeds always give the same ranks.
+pub fn void_and_cluster(size: u32, seed: u64) -> Vec<u32> {
+    let n = (size * size) as usize;
+    let mut field = EnergyField::new(size);
+
+    // Initial pattern: about a tenth of the cells, scattered at random
+    let mut state = seed;
+    let mut placed = 0;
+    while placed < (n / 10).max(1) {
+        let p = (splitmix64(&mut state) % n as u64) as usize;
+        if !field.on[p] {
+            field.toggle(p);
+            placed += 1;
+        }
+    }
+
+    // Relax it: move the tightest cluster into the largest void until that changes nothing
+    for _ in 0..n {
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster);
+        let void = field.largest_void();
+        field.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+    let prototype = field.on.clone();
+    let ones = placed;
+
+    let mut ranks = vec![0; n];
+
+    // Phase 1: take clusters out of the prototype, ranking downwards
+    let mut rank = ones;
+    while rank > 0 {
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster);
+        rank -= 1;
+        ranks[cluster] = rank as u32;
+    }
+
+    // Phases 2 and 3: from the prototype, fill voids upwards. Once past half full the
+    // tightest cluster of empty cells is the cell with the least energy from filled ones,
+    // which is the same search, so one loop covers both phases.
+    field = EnergyField::new(size);
+    for (p, _) in prototype.iter().enumerate().filter(|(_, &on)| on) {
+        field.toggle(p);
+    }
+    for rank in ones..n {
+        let void = field.largest_void();
+        field.toggle(void);
+        ranks[void] = rank as u32;
+    }
+
+    ranks
+}
+
+// Default-seed maps generated so far, keyed by size. Other seeds are caller-chosen and
+// unbounded, so they are generated per call rather than kept
+type Cache = Mutex<HashMap<u32, Arc<ThresholdMap>>>;
+
+pub(crate) fn cached(size: u32, seed: u64) -> Arc<ThresholdMap> {
+    let generate = || Arc::new(ThresholdMap::from_ranks(size, size, &void_and_cluster(size, seed)));
+    if seed != 0 {
+        return generate();
+    }
+    static CACHE: OnceLock<Cache> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(map) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&size) {
+        return Arc::clone(map);
+    }
+    // Generate outside the lock so one slow size does not hold up the others
+    let map = generate();
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(cache.entry(size).or_insert(map))
+}
+
+// Binary pattern plus, for every cell, the Gaussian-weighted count of set cells around it
+struct EnergyField {
+    size: u32,
+    on: Vec<bool>,
+    energy: Vec<f32>,
+    // Gaussian weight by toroidal offset, so updates are a single pass
+    kernel: Vec<f32>,
+}
+
+impl EnergyField {
+    fn new(size: u32) -> Self {
+        let n = (size * size) as usize;
+        let mut kernel = vec![0.0; n];
+        for dy in 0..size {
+            for dx in 0..size {
+                // Shortest distance on the torus, so the map tiles seamlessly
+                let wx = dx.min(size - dx) as f32;
+                let wy = dy.min(size - dy) as f32;
+                kernel[(dy * size + dx) as usize] = (-(wx * wx + wy * wy) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+        EnergyField {
+            size,
+            on: vec![false; n],
+            energy: vec![0.0; n],
+            kernel,
+        }
+    }
+
+    fn toggle(&mut self, p: usize) {
+        let sign = if self.on[p] { -1.0 } else { 1.0 };
+        self.on[p] = !self.on[p];
+        let size = self.size as usize;
+        let (px, py) = (p % size, p / size);
+        for qy in 0..size {
+            let dy = (qy + size - py) % size;
+            for qx in 0..size {
+                let dx = (qx + size - px) % size;
+                self.energy[qy * size + qx] += sign * self.kernel[dy * size + dx];
+            }
+        }
+    }
+
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    // First cell in state `on` whose energy beats every other by `better`
+    fn extreme(&self, on: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best: Option<usize> = None;
+        for p in 0..self.on.len() {
+            if self.on[p] == on && best.is_none_or(|b| better(self.energy[p], self.energy[b])) {
+                best = Some(p);
+            }
+        }
+        best.expect("pattern has cells in both states")
+    }
+}
+
+// SplitMix64 step: small, fast and good enough for seeding patterns
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,94 @@
+//! CMYK colour separation, with each separation screened at its own angle.
+
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use serde::Deserialize;
+
+use crate::halftone::Halftone;
+use crate::levels::Levels;
+use crate::Ditherer;
+
+/// Process inks in separation order.
+pub const INKS: [&str; 4] = ["cyan", "magenta", "yellow", "black"];
+
+/// Classic screen angles in degrees, in [`INKS`] order: the three dark inks 30° apart
+/// to keep moiré down, and yellow, the least visible, on the remaining 0°.
+pub const SCREEN_ANGLES: [f32; 4] = [15.0, 75.0, 0.0, 45.0];
+
+/// Whether the pipeline collapses to gray or separates into CMYK.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Gray,
+    Cmyk,
+}
+
+/// What a CMYK run returns.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CmykOutput {
+    /// The screened separations printed over each other on white.
+    #[default]
+    Composite,
+    /// One screened plate per ink, black where ink goes down, as for film output.
+    Separations,
+}
+
+/// Four-colour halftone: RGB to CMYK with gray component replacement, then one screen per ink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmykHalftone {
+    /// Ruling, resolution and dot shape shared by all four screens; the angle and levels are ignored.
+    pub screen: Halftone,
+    /// Fraction of the gray component printed with black ink (black generation).
+    pub gcr: f32,
+    /// Fraction of the gray component taken out of cyan, magenta and yellow.
+    pub ucr: f32,
+}
+
+impl Default for CmykHalftone {
+    fn default() -> Self {
+        CmykHalftone {
+            screen: Halftone::default(),
+            gcr: 0.5,
+            ucr: 0.5,
+        }
+    }
+}
+
+impl CmykHalftone {
+    /// Continuous-tone separations in [`INKS`] order; 0 is full ink, 255 is none.
+    pub fn separate(&self, img: &RgbImage) -> [GrayImage; 4] {
+        let (width, height) = img.dimensions();
+        let mut plates = [(); 4].map(|_| GrayImage::new(width, height));
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let [c, m, y_ink] = pixel.0.map(|v| 1.0 - v as f32 / 255.0);
+            let gray = c.min(m).min(y_ink);
+            let black = self.gcr * gray;
+            let removed = self.ucr * gray;
+            let inks = [c - removed, m - removed, y_ink - removed, black];
+            for (plate, ink) in plates.iter_mut().zip(inks) {
+                plate.put_pixel(x, y, Luma([((1.0 - ink.clamp(0.0, 1.0)) * 255.0).round() as u8]));
+            }
+        }
+        plates
+    }
+
+    /// Screened separations in [`INKS`] order, each at its angle from [`SCREEN_ANGLES`].
+    pub fn plates(&self, img: &RgbImage) -> [GrayImage; 4] {
+        let mut plates = self.separate(img);
+        for (plate, angle) in plates.iter_mut().zip(SCREEN_ANGLES) {
+            *plate = Halftone { angle, levels: Levels::default(), ..self.screen.clone() }.dither(plate);
+        }
+        plates
+    }
+
+    /// Overprint screened plates on white paper: each ink absorbs its complementary primary.
+    pub fn composite(plates: &[GrayImage; 4]) -> RgbImage {
+        let [cyan, magenta, yellow, black] = plates;
+        RgbImage::from_fn(cyan.width(), cyan.height(), |x, y| {
+            let paper = |plate: &GrayImage| plate.get_pixel(x, y)[0] as u16;
+            let k = paper(black);
+            Rgb([cyan, magenta, yellow].map(|plate| (paper(plate) * k / 255) as u8))
+        })
+    }
+}
@@ -0,0 +1,402 @@
+use image::{GrayImage, Luma, RgbImage};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::levels::Levels;
+use crate::metric::{ColorMetric, Matcher};
+use crate::palette::Palette;
+use crate::{Ditherer, PaletteDitherer};
+
+/// Settings shared by the error-diffusion algorithms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffusionOptions {
+    /// Gray at or above which a pixel turns white, as in [`crate::Threshold`]; two-level output only.
+    pub threshold: u8,
+    /// Fraction of the quantisation error passed on, 0.0 to 1.0.
+    pub strength: f32,
+    /// Walk odd rows right-to-left with the kernel mirrored. On in [`DiffusionOptions::default`];
+    /// [`crate::Algorithm::serpentine_default`] gives each algorithm's own default.
+    pub serpentine: bool,
+    /// Gray levels the output may use.
+    pub levels: Levels,
+    /// Edge enhancement by threshold modulation: each pixel's output level is picked as if
+    /// it differed from its 3x3 neighbourhood this many times more, while the error passed
+    /// on is still measured against the source. Sharpens edges without altering the image. Off at 0.0.
+    pub edge_boost: f32,
+}
+
+impl Default for DiffusionOptions {
+    fn default() -> Self {
+        DiffusionOptions {
+            threshold: 128,
+            strength: 1.0,
+            serpentine: true,
+            levels: Levels::default(),
+            edge_boost: 0.0,
+        }
+    }
+}
+
+/// One neighbour that receives `weight / divisor` of the error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tap {
+    pub dx: i32,
+    pub dy: i32,
+    pub weight: f32,
+}
+
+const fn tap(dx: i32, dy: i32, weight: f32) -> Tap {
+    Tap { dx, dy, weight }
+}
+
+/// An error-diffusion kernel: where the error goes, relative to the current pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    pub taps: Cow<'static, [Tap]>,
+    pub divisor: f32,
+}
+
+impl Kernel {
+    const fn fixed(taps: &'static [Tap], divisor: f32) -> Self {
+        Kernel { taps: Cow::Borrowed(taps), divisor }
+    }
+}
+
+/// Largest custom kernel accepted, in rows and in columns.
+pub const MAX_KERNEL_SIZE: usize = 9;
+
+/// A kernel written out as a weight matrix, the way it is usually printed:
+/// `"*"` marks the current pixel and `divisor` defaults to the sum of the weights.
+/// Floyd-Steinberg, for example, is
+///
+/// ```json
+/// { "matrix": [[0, "*", 7], [3, 5, 1]], "divisor": 16 }
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct KernelSpec {
+    pub matrix: Vec<Vec<KernelEntry>>,
+    pub divisor: Option<f32>,
+}
+
+/// A cell of a [`KernelSpec`] matrix: a weight, or the `"*"` origin marker.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum KernelEntry {
+    Weight(f32),
+    Marker(String),
+}
+
+/// Why a [`KernelSpec`] cannot be used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KernelError {
+    Empty,
+    Oversized { rows: usize, cols: usize },
+    Ragged,
+    BadMarker(String),
+    MissingOrigin,
+    MultipleOrigins,
+    NonCausal { dx: i32, dy: i32 },
+    NonFinite,
+    ZeroSum,
+    BadDivisor(f32),
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KernelError::Empty => write!(f, "kernel matrix is empty"),
+            KernelError::Oversized { rows, cols } => write!(
+                f,
+                "kernel is {}x{}, larger than the {}x{} limit",
+                rows, cols, MAX_KERNEL_SIZE, MAX_KERNEL_SIZE
+            ),
+            KernelError::Ragged => write!(f, "kernel rows must all have the same length"),
+            KernelError::BadMarker(marker) => write!(f, "unexpected `{}` in kernel, only `*` may mark the origin", marker),
+            KernelError::MissingOrigin => write!(f, "kernel has no `*` marking the current pixel"),
+            KernelError::MultipleOrigins => write!(f, "kernel has more than one `*`"),
+            KernelError::NonCausal { dx, dy } => write!(
+                f,
+                "weight at offset ({}, {}) points at a pixel that has already been processed",
+                dx, dy
+            ),
+            KernelError::NonFinite => write!(f, "kernel weights must be finite numbers"),
+            KernelError::ZeroSum => write!(f, "kernel weights must add up to more than zero"),
+            KernelError::BadDivisor(divisor) => write!(f, "divisor must be a positive number, got {}", divisor),
+        }
+    }
+}
+
+impl std::error::Error for KernelError {}
+
+impl KernelSpec {
+    /// Check the matrix and turn it into a [`Kernel`].
+    pub fn build(&self) -> Result<Kernel, KernelError> {
+        let rows = self.matrix.len();
+        let cols = self.matrix.first().map_or(0, Vec::len);
+        if rows == 0 || cols == 0 {
+            return Err(KernelError::Empty);
+        }
+        if rows > MAX_KERNEL_SIZE || cols > MAX_KERNEL_SIZE {
+            return Err(KernelError::Oversized { rows, cols });
+        }
+        if self.matrix.iter().any(|row| row.len() != cols) {
+            return Err(KernelError::Ragged);
+        }
+
+        let mut origin = None;
+        for (row, entries) in self.matrix.iter().enumerate() {
+            for (col, entry) in entries.iter().enumerate() {
+                match entry {
+                    KernelEntry::Marker(marker) if marker.as_str() != "*" => {
+                        return Err(KernelError::BadMarker(marker.clone()));
+                    }
+                    KernelEntry::Marker(_) if origin.is_some() => return Err(KernelError::MultipleOrigins),
+                    KernelEntry::Marker(_) => origin = Some((col as i32, row as i32)),
+                    KernelEntry::Weight(_) => {}
+                }
+            }
+        }
+        let (ox, oy) = origin.ok_or(KernelError::MissingOrigin)?;
+
+        let mut taps = Vec::new();
+        for (row, entries) in self.matrix.iter().enumerate() {
+            for (col, entry) in entries.iter().enumerate() {
+                let KernelEntry::Weight(weight) = *entry else { continue };
+                if !weight.is_finite() {
+                    return Err(KernelError::NonFinite);
+                }
+                if weight == 0.0 {
+                    continue;
+                }
+                let (dx, dy) = (col as i32 - ox, row as i32 - oy);
+                // Only pixels after the current one in scan order may receive error
+                if dy < 0 || (dy == 0 && dx <= 0) {
+                    return Err(KernelError::NonCausal { dx, dy });
+                }
+                taps.push(tap(dx, dy, weight));
+            }
+        }
+
+        let sum: f32 = taps.iter().map(|t| t.weight).sum();
+        if sum <= 0.0 {
+            return Err(KernelError::ZeroSum);
+        }
+        let divisor = self.divisor.unwrap_or(sum);
+        if !divisor.is_finite() || divisor <= 0.0 {
+            return Err(KernelError::BadDivisor(divisor));
+        }
+
+        Ok(Kernel { taps: Cow::Owned(taps), divisor })
+    }
+}
+
+/// The classic error-diffusion kernels.
+pub mod kernels {
+    use super::{tap, Kernel};
+
+    pub const FLOYD_STEINBERG: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 7.0),
+            tap(-1, 1, 3.0), tap(0, 1, 5.0), tap(1, 1, 1.0),
+        ],
+        16.0,
+    );
+
+    pub const JARVIS_JUDICE_NINKE: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 7.0), tap(2, 0, 5.0),
+            tap(-2, 1, 3.0), tap(-1, 1, 5.0), tap(0, 1, 7.0), tap(1, 1, 5.0), tap(2, 1, 3.0),
+            tap(-2, 2, 1.0), tap(-1, 2, 3.0), tap(0, 2, 5.0), tap(1, 2, 3.0), tap(2, 2, 1.0),
+        ],
+        48.0,
+    );
+
+    pub const STUCKI: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 8.0), tap(2, 0, 4.0),
+            tap(-2, 1, 2.0), tap(-1, 1, 4.0), tap(0, 1, 8.0), tap(1, 1, 4.0), tap(2, 1, 2.0),
+            tap(-2, 2, 1.0), tap(-1, 2, 2.0), tap(0, 2, 4.0), tap(1, 2, 2.0), tap(2, 2, 1.0),
+        ],
+        42.0,
+    );
+
+    pub const BURKES: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 8.0), tap(2, 0, 4.0),
+            tap(-2, 1, 2.0), tap(-1, 1, 4.0), tap(0, 1, 8.0), tap(1, 1, 4.0), tap(2, 1, 2.0),
+        ],
+        32.0,
+    );
+
+    pub const SIERRA: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 5.0), tap(2, 0, 3.0),
+            tap(-2, 1, 2.0), tap(-1, 1, 4.0), tap(0, 1, 5.0), tap(1, 1, 4.0), tap(2, 1, 2.0),
+            tap(-1, 2, 2.0), tap(0, 2, 3.0), tap(1, 2, 2.0),
+        ],
+        32.0,
+    );
+
+    pub const TWO_ROW_SIERRA: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 4.0), tap(2, 0, 3.0),
+            tap(-2, 1, 1.0), tap(-1, 1, 2.0), tap(0, 1, 3.0), tap(1, 1, 2.0), tap(2, 1, 1.0),
+        ],
+        16.0,
+    );
+
+    pub const SIERRA_LITE: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 2.0),
+            tap(-1, 1, 1.0), tap(0, 1, 1.0),
+        ],
+        4.0,
+    );
+
+    // Only 6/8 of the error is passed on, which keeps highlights and shadows clean
+    pub const ATKINSON: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 1.0), tap(2, 0, 1.0),
+            tap(-1, 1, 1.0), tap(0, 1, 1.0), tap(1, 1, 1.0),
+            tap(0, 2, 1.0),
+        ],
+        8.0,
+    );
+
+    pub const FAN: Kernel = Kernel::fixed(
+        &[
+            tap(1, 0, 7.0),
+            tap(-2, 1, 1.0), tap(-1, 1, 3.0), tap(0, 1, 5.0),
+        ],
+        16.0,
+    );
+}
+
+// Output level for an error-diffusion pixel, given as a tone
+fn quantize(tone: f32, opts: &DiffusionOptions) -> u8 {
+    let levels = &opts.levels;
+    if levels.len() == 2 {
+        return if tone < levels.tone(opts.threshold as f32) { levels.darkest() } else { levels.lightest() };
+    }
+    levels.nearest_tone(tone)
+}
+
+/// Error diffusion with any [`Kernel`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorDiffusion {
+    pub kernel: Kernel,
+    pub options: DiffusionOptions,
+}
+
+impl ErrorDiffusion {
+    /// Diffusion with [`DiffusionOptions::default`], whatever the kernel.
+    pub fn new(kernel: Kernel) -> Self {
+        ErrorDiffusion { kernel, options: DiffusionOptions::default() }
+    }
+}
+
+impl Ditherer for ErrorDiffusion {
+    fn dither(&self, img: &GrayImage) -> GrayImage {
+        let opts = &self.options;
+        let (width, height) = img.dimensions();
+        // Error is carried at full precision, in the levels' tone space (linear light or
+        // sRGB code values), and only rounded when a pixel is written out. Tones are clamped to the
+        // output range first: a pixel the levels cannot reach would otherwise shed the same error
+        // every step, and it would pile up and bleed across edges
+        let levels = &opts.levels;
+        let (darkest, lightest) = (levels.tone_of(levels.darkest()), levels.tone_of(levels.lightest()));
+        let mut error_buf: Vec<f32> =
+            img.pixels().map(|p| levels.tone(p[0] as f32).clamp(darkest, lightest)).collect();
+        let mut dithered = GrayImage::new(width, height);
+        let boost: Vec<f32> = if opts.edge_boost == 0.0 {
+            Vec::new()
+        } else {
+            high_pass(&error_buf, width, height).into_iter().map(|h| h * opts.edge_boost).collect()
+        };
+
+        for y in 0..height {
+            // Serpentine scanning walks odd rows right-to-left with the kernel mirrored
+            let reverse = opts.serpentine && y % 2 == 1;
+            let dir = if reverse { -1 } else { 1 };
+            for i in 0..width {
+                let x = if reverse { width - 1 - i } else { i };
+                let index = (y * width + x) as usize;
+                let old_pixel = error_buf[index];
+                let new_pixel = quantize(old_pixel + boost.get(index).copied().unwrap_or(0.0), opts);
+                let error = (old_pixel - opts.levels.tone_of(new_pixel)) * opts.strength / self.kernel.divisor;
+                dithered.put_pixel(x, y, Luma([new_pixel]));
+
+                for tap in self.kernel.taps.iter() {
+                    let nx = x as i32 + tap.dx * dir;
+                    let ny = y as i32 + tap.dy;
+                    if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
+                        error_buf[(ny as u32 * width + nx as u32) as usize] += error * tap.weight;
+                    }
+                }
+            }
+        }
+
+        dithered
+    }
+}
+
+// Each value minus the mean of its 3x3 neighbourhood, edges extended
+fn high_pass(values: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let (w, h) = (width as i64, height as i64);
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let mut sum = 0.0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (nx, ny) = ((x + dx).clamp(0, w - 1), (y + dy).clamp(0, h - 1));
+                    sum += values[(ny * w + nx) as usize];
+                }
+            }
+            values[(y * w + x) as usize] - sum / 9.0
+        })
+        .collect()
+}
+
+impl PaletteDitherer for ErrorDiffusion {
+    fn dither_palette(&self, img: &RgbImage, palette: &Palette, metric: ColorMetric) -> RgbImage {
+        let opts = &self.options;
+        let (width, height) = img.dimensions();
+        // One error buffer per channel, carried at full precision and in the same tone space as the gray path
+        let levels = &opts.levels;
+        let mut error_buf: Vec<[f32; 3]> = img.pixels().map(|p| p.0.map(|v| levels.tone(v as f32))).collect();
+        let mut dithered = RgbImage::new(width, height);
+        let mut matcher = Matcher::new(palette, metric);
+
+        for y in 0..height {
+            let reverse = opts.serpentine && y % 2 == 1;
+            let dir = if reverse { -1 } else { 1 };
+            for i in 0..width {
+                let x = if reverse { width - 1 - i } else { i };
+                // Clamp before matching so error piling up against a colour the palette
+                // cannot reach does not run away
+                let old_pixel = error_buf[(y * width + x) as usize].map(|v| v.clamp(0.0, 255.0));
+                let new_pixel = palette.colors()[matcher.nearest(old_pixel.map(|v| levels.gray(v)))];
+                dithered.put_pixel(x, y, new_pixel);
+
+                let error: [f32; 3] = std::array::from_fn(|c| {
+                    (old_pixel[c] - levels.tone_of(new_pixel[c])) * opts.strength / self.kernel.divisor
+                });
+                for tap in self.kernel.taps.iter() {
+                    let nx = x as i32 + tap.dx * dir;
+                    let ny = y as i32 + tap.dy;
+                    if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
+                        let target = &mut error_buf[(ny as u32 * width + nx as u32) as usize];
+                        for c in 0..3 {
+                            target[c] += error[c] * tap.weight;
+                        }
+                    }
+                }
+            }
+        }
+
+        dithered
+    }
+}
@@ -0,0 +1,156 @@
+//! Colour-to-gray conversion: luma under several standards, lightness, and channel mixing.
+
+use image::{DynamicImage, GrayImage, Luma};
+use serde::Deserialize;
+use std::fmt;
+
+use crate::color::{lightness, srgb8_to_linear};
+
+/// How a colour image is reduced to gray before dithering.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Grayscale {
+    /// Rec. 601 luma, as used by SD video and JPEG.
+    Rec601,
+    /// Rec. 709 luma; the long-standing default.
+    #[default]
+    Rec709,
+    /// Rec. 2100 (and Rec. 2020) luma, for wide-gamut sources.
+    Rec2100,
+    /// CIE L* of the Rec. 709 luminance, scaled to 0..255.
+    Lightness,
+    /// Mean of red, green and blue.
+    Average,
+    Red,
+    Green,
+    Blue,
+    /// Brightest channel: saturated colours come out light.
+    Max,
+    /// Darkest channel: saturated colours come out dark.
+    Min,
+    /// Channel mixer: red, green and blue weights, scaled to sum to one.
+    Weights([f32; 3]),
+}
+
+const REC601: [f32; 3] = [0.299, 0.587, 0.114];
+const REC709: [f32; 3] = [0.2126, 0.7152, 0.0722];
+const REC2100: [f32; 3] = [0.2627, 0.678, 0.0593];
+
+impl Grayscale {
+    /// Every conversion that has a name; channel weights are given as numbers instead.
+    pub const NAMED: [Grayscale; 10] = [
+        Grayscale::Rec601,
+        Grayscale::Rec709,
+        Grayscale::Rec2100,
+        Grayscale::Lightness,
+        Grayscale::Average,
+        Grayscale::Red,
+        Grayscale::Green,
+        Grayscale::Blue,
+        Grayscale::Max,
+        Grayscale::Min,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Grayscale::Rec601 => "rec601",
+            Grayscale::Rec709 => "rec709",
+            Grayscale::Rec2100 => "rec2100",
+            Grayscale::Lightness => "lightness",
+            Grayscale::Average => "average",
+            Grayscale::Red => "red",
+            Grayscale::Green => "green",
+            Grayscale::Blue => "blue",
+            Grayscale::Max => "max",
+            Grayscale::Min => "min",
+            Grayscale::Weights(_) => "weights",
+        }
+    }
+
+    pub fn convert(self, img: &DynamicImage) -> GrayImage {
+        // The image crate's own conversion is Rec. 709 on encoded values; keep it bit for bit
+        if self == Grayscale::Rec709 {
+            return img.to_luma8();
+        }
+        let rgb = img.to_rgb8();
+        GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| Luma([self.gray(rgb.get_pixel(x, y).0)]))
+    }
+
+    fn gray(self, [r, g, b]: [u8; 3]) -> u8 {
+        let weighted = |[wr, wg, wb]: [f32; 3]| {
+            let value = wr * r as f32 + wg * g as f32 + wb * b as f32;
+            value.round().clamp(0.0, 255.0) as u8
+        };
+        match self {
+            Grayscale::Rec601 => weighted(REC601),
+            Grayscale::Rec709 => weighted(REC709),
+            Grayscale::Rec2100 => weighted(REC2100),
+            Grayscale::Lightness => {
+                let [lr, lg, lb] = [r, g, b].map(srgb8_to_linear);
+                let luminance = REC709[0] * lr + REC709[1] * lg + REC709[2] * lb;
+                (lightness(luminance) * 2.55).round().clamp(0.0, 255.0) as u8
+            }
+            Grayscale::Average => ((r as u16 + g as u16 + b as u16 + 1) / 3) as u8,
+            Grayscale::Red => r,
+            Grayscale::Green => g,
+            Grayscale::Blue => b,
+            Grayscale::Max => r.max(g).max(b),
+            Grayscale::Min => r.min(g).min(b),
+            Grayscale::Weights(weights) => {
+                let sum: f32 = weights.iter().sum();
+                weighted(weights.map(|w| w / sum))
+            }
+        }
+    }
+}
+
+/// A conversion as given in a request: a name, or `[red, green, blue]` channel weights.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GrayscaleSpec {
+    Named(String),
+    Weights([f32; 3]),
+}
+
+impl GrayscaleSpec {
+    pub fn build(&self) -> Result<Grayscale, GrayscaleError> {
+        match self {
+            GrayscaleSpec::Named(name) => {
+                let wanted = name.trim().to_ascii_lowercase();
+                Grayscale::NAMED
+                    .into_iter()
+                    .find(|method| method.name() == wanted)
+                    .ok_or_else(|| GrayscaleError::UnknownName(name.clone()))
+            }
+            // Negative weights are allowed, as in any channel mixer, but the mix must not cancel out
+            GrayscaleSpec::Weights(weights) => {
+                let sum: f32 = weights.iter().sum();
+                if weights.iter().all(|w| w.is_finite()) && sum > 0.0 {
+                    Ok(Grayscale::Weights(*weights))
+                } else {
+                    Err(GrayscaleError::BadWeights)
+                }
+            }
+        }
+    }
+}
+
+/// Why a grayscale conversion was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrayscaleError {
+    UnknownName(String),
+    BadWeights,
+}
+
+impl fmt::Display for GrayscaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrayscaleError::UnknownName(given) => {
+                let valid: Vec<&str> = Grayscale::NAMED.iter().map(|method| method.name()).collect();
+                write!(f, "unknown conversion `{}`, expected one of: {}", given, valid.join(", "))
+            }
+            GrayscaleError::BadWeights => f.write_str("channel weights must be finite and sum to more than zero"),
+        }
+    }
+}
+
+impl std::error::Error for GrayscaleError {}
@@ -0,0 +1,126 @@
+//! Amplitude-modulated (clustered-dot) halftone screens, as used for offset and screen printing.
+
+use image::GrayImage;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::levels::Levels;
+use crate::ordered::{Ordered, ThresholdMap};
+use crate::Ditherer;
+
+// Samples per side of one screen cell; the dot grows through this many steps squared
+const CELL_SAMPLES: u32 = 64;
+
+/// Shape of the halftone dot as it grows from highlight to shadow.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DotShape {
+    Round,
+    Ellipse,
+    Square,
+    Line,
+    Diamond,
+}
+
+impl DotShape {
+    pub const ALL: [DotShape; 5] = [
+        DotShape::Round,
+        DotShape::Ellipse,
+        DotShape::Square,
+        DotShape::Line,
+        DotShape::Diamond,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DotShape::Round => "round",
+            DotShape::Ellipse => "ellipse",
+            DotShape::Square => "square",
+            DotShape::Line => "line",
+            DotShape::Diamond => "diamond",
+        }
+    }
+
+    // Spot function over the cell, x and y in -1..1 with the dot centre at 0;
+    // lower values turn dark first
+    fn spot(self, x: f32, y: f32) -> f32 {
+        let (ax, ay) = (x.abs(), y.abs());
+        match self {
+            // Euclidean dot: round in the highlights, meeting as a checkerboard at 50%
+            // and inverting to round white holes in the shadows
+            DotShape::Round if ax + ay <= 1.0 => x * x + y * y,
+            DotShape::Round => 2.0 - (1.0 - ax).powi(2) - (1.0 - ay).powi(2),
+            DotShape::Ellipse => x * x + (y * y) / 0.49,
+            DotShape::Square => ax.max(ay),
+            DotShape::Line => ay,
+            DotShape::Diamond => ax + ay,
+        }
+    }
+
+    /// One screen cell as a threshold map: the dot centre has the highest threshold
+    /// and so darkens first, and every threshold covers an equal share of the cell.
+    pub fn cell(self) -> ThresholdMap {
+        let n = CELL_SAMPLES;
+        let spots: Vec<f32> = (0..n * n)
+            .map(|i| {
+                let x = ((i % n) as f32 + 0.5) / n as f32 * 2.0 - 1.0;
+                let y = ((i / n) as f32 + 0.5) / n as f32 * 2.0 - 1.0;
+                self.spot(x, y)
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..spots.len()).collect();
+        order.sort_by(|&a, &b| spots[b].total_cmp(&spots[a]).then(a.cmp(&b)));
+        let mut ranks = vec![0; order.len()];
+        for (rank, cell) in order.into_iter().enumerate() {
+            ranks[cell] = rank as u32;
+        }
+        ThresholdMap::from_ranks(n, n, &ranks)
+    }
+}
+
+/// Clustered-dot halftone screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Halftone {
+    /// Screen ruling, in lines per inch.
+    pub lpi: f32,
+    /// Resolution of the output, in dots per inch.
+    pub dpi: f32,
+    /// Screen angle in degrees.
+    pub angle: f32,
+    pub shape: DotShape,
+    /// Gray levels the output may use. With more than two, each cell steps between the pair
+    /// either side of the pixel; in linear light, dots are sized by the light they block.
+    pub levels: Levels,
+}
+
+impl Default for Halftone {
+    fn default() -> Self {
+        Halftone {
+            lpi: 60.0,
+            dpi: 300.0,
+            angle: 45.0,
+            shape: DotShape::Round,
+            levels: Levels::default(),
+        }
+    }
+}
+
+impl Halftone {
+    /// The screen as a scaled, rotated threshold map.
+    pub fn screen(&self) -> Ordered {
+        let cell_pixels = self.dpi / self.lpi;
+        Ordered {
+            map: Arc::new(self.shape.cell()),
+            offset: (0, 0),
+            rotation: self.angle,
+            scale: CELL_SAMPLES as f32 / cell_pixels,
+            levels: self.levels.clone(),
+        }
+    }
+}
+
+impl Ditherer for Halftone {
+    fn dither(&self, img: &GrayImage) -> GrayImage {
+        self.screen().dither(img)
+    }
+}
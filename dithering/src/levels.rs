@@ -0,0 +1,196 @@
+//! Output gray levels: evenly spaced, or an arbitrary set such as an e-ink panel's.
+
+use serde::Deserialize;
+use std::fmt;
+
+use crate::color::{linear_to_srgb, srgb8_to_linear, srgb_to_linear};
+
+/// Most levels an output can have: every 8-bit gray.
+pub const MAX_LEVELS: usize = 256;
+
+/// The gray values an output may use, sorted and distinct, and whether pixels are
+/// compared against them in linear light or directly on their sRGB-encoded values.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Levels {
+    values: Vec<u8>,
+    linear: bool,
+}
+
+impl Levels {
+    /// `count` evenly spaced levels from black to white.
+    pub fn uniform(count: u16) -> Self {
+        assert!((2..=MAX_LEVELS as u16).contains(&count), "level count out of range: {}", count);
+        let steps = (count - 1) as f32;
+        Levels {
+            values: (0..count).map(|i| (i as f32 * 255.0 / steps).round() as u8).collect(),
+            linear: false,
+        }
+    }
+
+    /// Levels at the given gray values, in any order; duplicates are dropped.
+    pub fn new(mut values: Vec<u8>) -> Result<Self, LevelsError> {
+        values.sort_unstable();
+        values.dedup();
+        if values.len() < 2 {
+            return Err(LevelsError::TooFew);
+        }
+        Ok(Levels { values, linear: false })
+    }
+
+    /// Compare in linear light: a pixel between two levels is split between them by
+    /// light emitted, not by code value, so dithered midtones keep their brightness.
+    pub fn linear_light(self, linear: bool) -> Self {
+        Levels { linear, ..self }
+    }
+
+    pub fn is_linear(&self) -> bool {
+        self.linear
+    }
+
+    pub fn values(&self) -> &[u8] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Always false; a level set has at least two entries.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn darkest(&self) -> u8 {
+        self.values[0]
+    }
+
+    pub fn lightest(&self) -> u8 {
+        self.values[self.values.len() - 1]
+    }
+
+    /// An sRGB-encoded gray, 0.0 to 255.0, in the space levels are compared in:
+    /// linear light scaled to 0.0 to 255.0, or unchanged.
+    pub fn tone(&self, gray: f32) -> f32 {
+        if self.linear {
+            srgb_to_linear(gray.clamp(0.0, 255.0) / 255.0) * 255.0
+        } else {
+            gray
+        }
+    }
+
+    /// [`Levels::tone`] of an output level, for measuring quantisation error.
+    pub fn tone_of(&self, level: u8) -> f32 {
+        if self.linear {
+            srgb8_to_linear(level) * 255.0
+        } else {
+            level as f32
+        }
+    }
+
+    /// Inverse of [`Levels::tone`]: back to an sRGB-encoded gray.
+    pub fn gray(&self, tone: f32) -> f32 {
+        if self.linear {
+            linear_to_srgb(tone.clamp(0.0, 255.0) / 255.0) * 255.0
+        } else {
+            tone
+        }
+    }
+
+    /// The level closest to `gray`.
+    pub fn nearest(&self, gray: f32) -> u8 {
+        self.nearest_tone(self.tone(gray))
+    }
+
+    /// The level closest to a value already converted by [`Levels::tone`].
+    pub fn nearest_tone(&self, tone: f32) -> u8 {
+        let (below, above) = self.bracket_tone(tone);
+        if tone - self.tone_of(below) <= self.tone_of(above) - tone {
+            below
+        } else {
+            above
+        }
+    }
+
+    /// The levels either side of `gray`; both the same outside the range.
+    pub fn bracket(&self, gray: f32) -> (u8, u8) {
+        self.bracket_tone(self.tone(gray))
+    }
+
+    fn bracket_tone(&self, tone: f32) -> (u8, u8) {
+        let above = self.values.partition_point(|&level| self.tone_of(level) < tone);
+        match above {
+            0 => (self.darkest(), self.darkest()),
+            i if i == self.values.len() => (self.lightest(), self.lightest()),
+            i => (self.values[i - 1], self.values[i]),
+        }
+    }
+
+    /// Ordered dithering between the two levels around `gray`: the upper one
+    /// wins when `gray`'s position between them, scaled to 0..255, beats `threshold`.
+    pub fn dither(&self, gray: f32, threshold: f32) -> u8 {
+        let tone = self.tone(gray);
+        let (below, above) = self.bracket_tone(tone);
+        if below == above {
+            return below;
+        }
+        let (low, high) = (self.tone_of(below), self.tone_of(above));
+        let position = (tone - low) / (high - low) * 255.0;
+        if position > threshold {
+            above
+        } else {
+            below
+        }
+    }
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels::uniform(2)
+    }
+}
+
+/// Output levels as given in a request: a count of evenly spaced grays, or the grays themselves.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum LevelsSpec {
+    Count(u16),
+    Values(Vec<u8>),
+}
+
+impl LevelsSpec {
+    /// Number of levels asked for, before duplicates are dropped.
+    pub fn count(&self) -> usize {
+        match self {
+            LevelsSpec::Count(count) => *count as usize,
+            LevelsSpec::Values(values) => values.len(),
+        }
+    }
+
+    pub fn build(&self) -> Result<Levels, LevelsError> {
+        match self {
+            LevelsSpec::Count(count) if (2..=MAX_LEVELS).contains(&(*count as usize)) => Ok(Levels::uniform(*count)),
+            LevelsSpec::Count(count) if *count < 2 => Err(LevelsError::TooFew),
+            LevelsSpec::Count(count) => Err(LevelsError::TooMany(*count as usize)),
+            LevelsSpec::Values(values) if values.len() > MAX_LEVELS => Err(LevelsError::TooMany(values.len())),
+            LevelsSpec::Values(values) => Levels::new(values.clone()),
+        }
+    }
+}
+
+/// Why a level set was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelsError {
+    TooFew,
+    TooMany(usize),
+}
+
+impl fmt::Display for LevelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelsError::TooFew => f.write_str("need at least two distinct levels"),
+            LevelsError::TooMany(count) => write!(f, "{} levels given, at most {} allowed", count, MAX_LEVELS),
+        }
+    }
+}
+
+impl std::error::Error for LevelsError {}
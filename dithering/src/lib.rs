@@ -0,0 +1,57 @@
+//! Grayscale dithering: error diffusion, ordered and threshold algorithms
+//! behind a common [`Ditherer`] trait, and a [`Pipeline`] that adds colour modes
+//! such as CMYK halftone separation.
+
+use image::{DynamicImage, GrayImage, ImageResult, RgbImage};
+use std::path::Path;
+
+mod algorithm;
+pub mod blue_noise;
+pub mod cmyk;
+pub mod color;
+pub mod diffusion;
+pub mod grayscale;
+pub mod halftone;
+pub mod levels;
+pub mod metric;
+pub mod ordered;
+pub mod palette;
+pub mod pipeline;
+pub mod preprocess;
+pub mod quantize;
+pub mod threshold;
+
+pub use algorithm::{Algorithm, OutputMode, ParamError, ParamKind, ParamSpec, Params, UnknownAlgorithm};
+pub use cmyk::{CmykHalftone, CmykOutput, ColorMode};
+pub use diffusion::{kernels, DiffusionOptions, ErrorDiffusion, Kernel, KernelEntry, KernelError, KernelSpec, Tap};
+pub use grayscale::{Grayscale, GrayscaleError, GrayscaleSpec};
+pub use halftone::{DotShape, Halftone};
+pub use levels::{Levels, LevelsError, LevelsSpec};
+pub use metric::{ColorMetric, Matcher};
+pub use ordered::{MapError, Ordered, ThresholdMap, ThresholdMapSpec};
+pub use palette::{Palette, PaletteError, PaletteSpec};
+pub use pipeline::{Pipeline, Rendered};
+pub use preprocess::{CurveError, Equalize, EqualizeMode, Preprocess, Sharpen, SharpenMode, ToneCurve, ToneCurveSpec};
+pub use quantize::Quantizer;
+pub use threshold::Threshold;
+
+/// A dithering algorithm: turns a grayscale image into one with few tones.
+pub trait Ditherer: Send + Sync {
+    fn dither(&self, img: &GrayImage) -> GrayImage;
+}
+
+/// A colour dithering algorithm: maps every pixel to an entry of a palette,
+/// matching colours under `metric`.
+pub trait PaletteDitherer: Send + Sync {
+    fn dither_palette(&self, img: &RgbImage, palette: &Palette, metric: ColorMetric) -> RgbImage;
+}
+
+/// Convert an image to grayscale with the default conversion, Rec. 709 luma.
+pub fn to_grayscale(img: &DynamicImage) -> GrayImage {
+    Grayscale::default().convert(img)
+}
+
+/// Save image, picking the format from the file extension.
+pub fn save_image<P: AsRef<Path>>(img: &GrayImage, path: P) -> ImageResult<()> {
+    img.save(path)
+}
@@ -0,0 +1,276 @@
+//! Nearest-colour search under a choice of colour-difference metrics.
+
+use serde::Deserialize;
+
+use crate::color::{ciede2000, lab, oklab};
+use crate::palette::Palette;
+
+// Largest lightness weighting S_L in CIEDE2000, reached at L* 0 and 100. The rest of the
+// formula never goes negative, so ΔE00 is at least |ΔL*| / S_L
+const MAX_LIGHTNESS_WEIGHT: f32 = 1.75;
+// Bits per channel of the RGB cells CIEDE2000 remembers its last match for
+const HINT_BITS: u32 = 5;
+// 25^7, where CIEDE2000's chroma corrections turn over
+const POW25_7: f32 = 6_103_515_625.0;
+// Peak of CIEDE2000's hue weighting function T, a little over 1.572
+const MAX_HUE_WEIGHT: f32 = 1.58;
+// sin(2Δθ) in the rotation term at its largest Δθ, 30°
+const MAX_ROTATION_SIN: f32 = 0.867;
+
+/// How the distance between two colours is measured when matching to a palette.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorMetric {
+    /// Plain Euclidean distance on sRGB values.
+    #[default]
+    Rgb,
+    /// Euclidean sRGB with channels weighted by their Rec. 601 luma share.
+    LumaRgb,
+    /// CIE 1976 ΔE*ab: Euclidean distance in L*a*b*.
+    Cie76,
+    /// CIEDE2000 ΔE00, the most perceptually even and the slowest.
+    Ciede2000,
+    /// Euclidean distance in OKLab.
+    Oklab,
+}
+
+impl ColorMetric {
+    pub const ALL: [ColorMetric; 5] = [
+        ColorMetric::Rgb,
+        ColorMetric::LumaRgb,
+        ColorMetric::Cie76,
+        ColorMetric::Ciede2000,
+        ColorMetric::Oklab,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMetric::Rgb => "rgb",
+            ColorMetric::LumaRgb => "luma-rgb",
+            ColorMetric::Cie76 => "cie76",
+            ColorMetric::Ciede2000 => "ciede2000",
+            ColorMetric::Oklab => "oklab",
+        }
+    }
+
+    // Map a colour into a space where this metric is plain Euclidean distance;
+    // CIEDE2000 has no such space and works from L*a*b*
+    fn project(self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorMetric::Rgb => rgb,
+            ColorMetric::LumaRgb => {
+                let [r, g, b] = rgb;
+                [r * 0.299f32.sqrt(), g * 0.587f32.sqrt(), b * 0.114f32.sqrt()]
+            }
+            ColorMetric::Cie76 | ColorMetric::Ciede2000 => lab(rgb),
+            ColorMetric::Oklab => oklab(rgb),
+        }
+    }
+}
+
+/// Nearest-colour lookup for one palette and metric. Build one per image: the Euclidean
+/// metrics search a k-d tree. CIEDE2000 starts from the last match for a nearby colour, or
+/// else the CIE76 match from the same tree, then computes the real difference only for
+/// entries a cheap lower bound cannot rule out; the answer is still exact.
+pub struct Matcher {
+    metric: ColorMetric,
+    tree: KdTree,
+    // CIEDE2000 only: palette entries ordered by lightness, and their index in the palette
+    by_lightness: Vec<(LabPoint, usize)>,
+    lab: Vec<[f32; 3]>,
+    // Per RGB cell, the last match made in it, u16::MAX until first asked: nearby colours
+    // mostly share a match, and a good first guess narrows the search
+    hints: Vec<u16>,
+    // Reused per lookup: (lower bound, position in by_lightness) for the entries still in the running
+    bounds: Vec<(f32, usize)>,
+}
+
+impl Matcher {
+    pub fn new(palette: &Palette, metric: ColorMetric) -> Self {
+        let points: Vec<[f32; 3]> = palette
+            .colors()
+            .iter()
+            .map(|color| metric.project(color.0.map(f32::from)))
+            .collect();
+        let (by_lightness, lab, hints) = match metric {
+            ColorMetric::Ciede2000 => {
+                let mut by_lightness: Vec<(LabPoint, usize)> =
+                    points.iter().map(|&lab| LabPoint::new(lab)).zip(0..).collect();
+                by_lightness.sort_by(|a, b| a.0.lab[0].total_cmp(&b.0.lab[0]));
+                (by_lightness, points.clone(), vec![u16::MAX; 1 << (3 * HINT_BITS)])
+            }
+            _ => (Vec::new(), Vec::new(), Vec::new()),
+        };
+        Matcher {
+            metric,
+            tree: KdTree::new(&points),
+            by_lightness,
+            lab,
+            hints,
+            bounds: Vec::new(),
+        }
+    }
+
+    /// Index of the palette colour nearest to `rgb`, a colour with channels 0.0 to 255.0.
+    pub fn nearest(&mut self, rgb: [f32; 3]) -> usize {
+        if self.metric != ColorMetric::Ciede2000 {
+            return self.tree.nearest(self.metric.project(rgb));
+        }
+        let target = LabPoint::new(self.metric.project(rgb));
+        let [r, g, b] = rgb.map(|c| c.clamp(0.0, 255.0) as usize >> (8 - HINT_BITS));
+        let cell = (r << (2 * HINT_BITS)) | (g << HINT_BITS) | b;
+        let guess = match self.hints[cell] {
+            u16::MAX => self.tree.nearest(target.lab),
+            hint => hint as usize,
+        };
+        let mut best = (ciede2000(target.lab, self.lab[guess]), guess);
+        let entries = &self.by_lightness;
+
+        // Only entries within reach on lightness alone, and then only those whose bound
+        // beats the best so far, closest bound first
+        let reach = best.0 * MAX_LIGHTNESS_WEIGHT;
+        let low = entries.partition_point(|(point, _)| point.lab[0] < target.lab[0] - reach);
+        let high = entries.partition_point(|(point, _)| point.lab[0] <= target.lab[0] + reach);
+        self.bounds.clear();
+        self.bounds.extend(
+            (low..high)
+                .map(|k| (lower_bound(target, entries[k].0), k))
+                .filter(|&(bound, _)| bound <= best.0),
+        );
+        self.bounds.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        for &(bound, k) in &self.bounds {
+            if bound > best.0 {
+                break;
+            }
+            let (point, i) = entries[k];
+            let distance = ciede2000(target.lab, point.lab);
+            // Ties go to the earlier palette entry, as a linear scan would
+            if distance < best.0 || distance == best.0 && i < best.1 {
+                best = (distance, i);
+            }
+        }
+        self.hints[cell] = best.1 as u16;
+        best.1
+    }
+}
+
+// An L*a*b* colour with its chroma, for CIEDE2000 bounds
+#[derive(Debug, Clone, Copy)]
+struct LabPoint {
+    lab: [f32; 3],
+    chroma: f32,
+}
+
+impl LabPoint {
+    fn new(lab: [f32; 3]) -> Self {
+        LabPoint { lab, chroma: lab[1].hypot(lab[2]) }
+    }
+}
+
+// A lower bound on CIEDE2000 with no hue angles or trigonometry. It follows the formula up
+// to the hue term: ΔH'² is the a'b' distance squared less ΔC'², and S_H is at its largest
+// with T at its peak. The rotation term only matters near blue, which two colours on the
+// yellow side (b ≥ 0) cannot average to; elsewhere it is taken at its strongest
+fn lower_bound(p: LabPoint, q: LabPoint) -> f32 {
+    let ([l1, a1, b1], [l2, a2, b2]) = (p.lab, q.lab);
+    let c_bar = (p.chroma + q.chroma) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + POW25_7)).sqrt());
+    let (a1p, a2p) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1p, c2p) = ((a1p * a1p + b1 * b1).sqrt(), (a2p * a2p + b2 * b2).sqrt());
+    let c_bar_p = (c1p + c2p) / 2.0;
+
+    let l_bar = (l1 + l2) / 2.0 - 50.0;
+    let s_l = 1.0 + 0.015 * l_bar * l_bar / (20.0 + l_bar * l_bar).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * MAX_HUE_WEIGHT;
+    let l = (l2 - l1) / s_l;
+    let c = ((c2p - c1p) / s_c).abs();
+    let h = ((a2p - a1p).powi(2) + (b2 - b1).powi(2) - (c2p - c1p).powi(2)).max(0.0).sqrt() / s_h;
+    let r_t = if b1 >= 0.0 && b2 >= 0.0 {
+        0.0
+    } else {
+        2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + POW25_7)).sqrt() * MAX_ROTATION_SIN
+    };
+    // c² + h² - r_t c h is least at h = r_t c / 2, and grows from there
+    let h = h.max(r_t * c / 2.0);
+    // Shaved by a hair so float rounding never rules out an entry that ties
+    (l * l + c * c + h * h - r_t * c * h).max(0.0).sqrt() * 0.999
+}
+
+// A 3-d tree over palette points, split on the widest axis at the median
+struct KdTree {
+    nodes: Vec<KdNode>,
+}
+
+struct KdNode {
+    point: [f32; 3],
+    index: usize,
+    axis: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+impl KdTree {
+    fn new(points: &[[f32; 3]]) -> Self {
+        let mut tree = KdTree { nodes: Vec::with_capacity(points.len()) };
+        let mut indices: Vec<usize> = (0..points.len()).collect();
+        tree.build(points, &mut indices);
+        tree
+    }
+
+    fn build(&mut self, points: &[[f32; 3]], indices: &mut [usize]) -> Option<usize> {
+        if indices.is_empty() {
+            return None;
+        }
+        let axis = (0..3)
+            .max_by(|&a, &b| {
+                let range = |axis: usize| {
+                    let values = indices.iter().map(|&i| points[i][axis]);
+                    values.clone().fold(f32::NEG_INFINITY, f32::max) - values.fold(f32::INFINITY, f32::min)
+                };
+                range(a).total_cmp(&range(b))
+            })
+            .unwrap_or(0);
+        indices.sort_by(|&a, &b| points[a][axis].total_cmp(&points[b][axis]).then(a.cmp(&b)));
+        let mid = indices.len() / 2;
+        let node = self.nodes.len();
+        self.nodes.push(KdNode {
+            point: points[indices[mid]],
+            index: indices[mid],
+            axis,
+            left: None,
+            right: None,
+        });
+        let (lower, upper) = indices.split_at_mut(mid);
+        self.nodes[node].left = self.build(points, lower);
+        self.nodes[node].right = self.build(points, &mut upper[1..]);
+        Some(node)
+    }
+
+    fn nearest(&self, target: [f32; 3]) -> usize {
+        let mut best = (f32::INFINITY, usize::MAX);
+        if !self.nodes.is_empty() {
+            self.search(0, target, &mut best);
+        }
+        best.1
+    }
+
+    fn search(&self, node: usize, target: [f32; 3], best: &mut (f32, usize)) {
+        let KdNode { point, index, axis, left, right } = &self.nodes[node];
+        let distance: f32 = (0..3).map(|c| (point[c] - target[c]).powi(2)).sum();
+        // Ties go to the earlier palette entry, as a linear scan would
+        if distance < best.0 || distance == best.0 && *index < best.1 {
+            *best = (distance, *index);
+        }
+        let offset = target[*axis] - point[*axis];
+        let (near, far) = if offset < 0.0 { (left, right) } else { (right, left) };
+        if let Some(near) = near {
+            self.search(*near, target, best);
+        }
+        if let Some(far) = far {
+            if offset * offset <= best.0 {
+                self.search(*far, target, best);
+            }
+        }
+    }
+}
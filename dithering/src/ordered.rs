@@ -0,0 +1,287 @@
+use base64::{engine::general_purpose, Engine as _};
+use image::io::Reader as ImageReader;
+use image::{GrayImage, Luma, RgbImage};
+use serde::Deserialize;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::blue_noise;
+use crate::levels::Levels;
+use crate::metric::{ColorMetric, Matcher};
+use crate::palette::Palette;
+use crate::{Ditherer, PaletteDitherer};
+
+/// Matrix sizes accepted by [`ThresholdMap::bayer`].
+pub const BAYER_SIZES: [u32; 6] = [2, 4, 8, 16, 32, 64];
+
+/// Bayer index matrix of side `size`, row-major, built recursively from the 2x2 case:
+/// `M(2n) = [[4M, 4M + 2], [4M + 3, 4M + 1]]`.
+///
+/// Panics if `size` is not a power of two.
+pub fn bayer_matrix(size: u32) -> Vec<u32> {
+    assert!(size.is_power_of_two(), "Bayer matrix size must be a power of two, got {}", size);
+    let mut matrix = vec![0];
+    let mut n = 1;
+    while n < size {
+        let mut next = vec![0; (4 * n * n) as usize];
+        for y in 0..n {
+            for x in 0..n {
+                let base = 4 * matrix[(y * n + x) as usize];
+                for (qx, qy, offset) in [(0, 0, 0), (1, 0, 2), (0, 1, 3), (1, 1, 1)] {
+                    next[((y + qy * n) * 2 * n + x + qx * n) as usize] = base + offset;
+                }
+            }
+        }
+        matrix = next;
+        n *= 2;
+    }
+    matrix
+}
+
+/// Largest caller-supplied threshold map, in cells per side.
+pub const MAX_MAP_SIZE: u32 = 256;
+
+/// A caller-supplied threshold map: a matrix of levels, or a base64 grayscale image
+/// whose pixel values are used directly as thresholds.
+///
+/// ```json
+/// { "matrix": [[0, 2], [3, 1]] }
+/// { "image": "iVBORw0KGgo..." }
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMapSpec {
+    /// Levels from 0 up; the largest value in the matrix is the top of the scale.
+    Matrix(Vec<Vec<f32>>),
+    Image(String),
+}
+
+/// Why a [`ThresholdMapSpec`] cannot be used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    Empty,
+    Oversized { width: u32, height: u32 },
+    Ragged,
+    BadLevel(f32),
+    Base64(String),
+    Image(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Empty => write!(f, "threshold map is empty"),
+            MapError::Oversized { width, height } => write!(
+                f,
+                "threshold map is {}x{}, larger than the {}x{} limit",
+                width, height, MAX_MAP_SIZE, MAX_MAP_SIZE
+            ),
+            MapError::Ragged => write!(f, "threshold map rows must all have the same length"),
+            MapError::BadLevel(level) => write!(f, "threshold map levels must be finite and not negative, got {}", level),
+            MapError::Base64(e) => write!(f, "threshold map image is not valid base64: {}", e),
+            MapError::Image(e) => write!(f, "threshold map image could not be decoded: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl ThresholdMapSpec {
+    /// Check the map and turn it into a [`ThresholdMap`].
+    pub fn build(&self) -> Result<ThresholdMap, MapError> {
+        let (width, height, levels, max_level) = match self {
+            ThresholdMapSpec::Matrix(rows) => {
+                let width = rows.first().map_or(0, Vec::len) as u32;
+                if rows.iter().any(|row| row.len() as u32 != width) {
+                    return Err(MapError::Ragged);
+                }
+                let levels: Vec<f32> = rows.iter().flatten().copied().collect();
+                if let Some(&bad) = levels.iter().find(|l| !l.is_finite() || **l < 0.0) {
+                    return Err(MapError::BadLevel(bad));
+                }
+                let max_level = levels.iter().copied().fold(0.0, f32::max);
+                (width, rows.len() as u32, levels, max_level)
+            }
+            ThresholdMapSpec::Image(data) => {
+                let bytes = general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| MapError::Base64(e.to_string()))?;
+                let format = image::guess_format(&bytes).map_err(|e| MapError::Image(e.to_string()))?;
+                // Size up the header before decoding, so an oversized upload is never unpacked
+                let (width, height) = ImageReader::with_format(Cursor::new(&bytes), format)
+                    .into_dimensions()
+                    .map_err(|e| MapError::Image(e.to_string()))?;
+                check_size(width, height)?;
+                let img = image::load_from_memory_with_format(&bytes, format)
+                    .map_err(|e| MapError::Image(e.to_string()))?
+                    .to_luma8();
+                let levels = img.pixels().map(|p| p[0] as f32).collect();
+                (img.width(), img.height(), levels, 255.0)
+            }
+        };
+        check_size(width, height)?;
+        Ok(ThresholdMap::from_levels(width, height, &levels, max_level))
+    }
+}
+
+fn check_size(width: u32, height: u32) -> Result<(), MapError> {
+    if width == 0 || height == 0 {
+        return Err(MapError::Empty);
+    }
+    if width > MAX_MAP_SIZE || height > MAX_MAP_SIZE {
+        return Err(MapError::Oversized { width, height });
+    }
+    Ok(())
+}
+
+/// A threshold texture tiled across the image; a pixel turns white when it is above its cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdMap {
+    width: u32,
+    height: u32,
+    thresholds: Vec<f32>,
+}
+
+impl ThresholdMap {
+    /// Build a map from a permutation of `0..width * height`, lowest rank turning white first.
+    pub fn from_ranks(width: u32, height: u32, ranks: &[u32]) -> Self {
+        let levels: Vec<f32> = ranks.iter().map(|&rank| rank as f32).collect();
+        ThresholdMap::from_levels(width, height, &levels, (width * height - 1) as f32)
+    }
+
+    /// Build a map from levels `0..=max_level`, row-major.
+    pub fn from_levels(width: u32, height: u32, levels: &[f32], max_level: f32) -> Self {
+        // Centre each threshold in its band so n levels give n + 1 evenly spaced tones
+        let thresholds = levels
+            .iter()
+            .map(|&level| (level + 0.5) / (max_level + 1.0) * 255.0)
+            .collect();
+        ThresholdMap { width, height, thresholds }
+    }
+
+    pub fn bayer(size: u32) -> Self {
+        ThresholdMap::from_ranks(size, size, &bayer_matrix(size))
+    }
+
+    /// Void-and-cluster blue noise. The default seed 0 is generated once per size and then
+    /// shared; any other seed is generated afresh on every call.
+    pub fn blue_noise(size: u32, seed: u64) -> Arc<Self> {
+        blue_noise::cached(size, seed)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Threshold at map position `(x, y)`, tiling the map in every direction.
+    pub fn threshold(&self, x: i64, y: i64) -> f32 {
+        let x = x.rem_euclid(self.width as i64);
+        let y = y.rem_euclid(self.height as i64);
+        self.thresholds[(y * self.width as i64 + x) as usize]
+    }
+}
+
+/// Ordered dithering against a tiled [`ThresholdMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ordered {
+    pub map: Arc<ThresholdMap>,
+    /// Shift of the tiling, in map cells.
+    pub offset: (i32, i32),
+    /// Rotation of the tiling around the image origin, in degrees clockwise.
+    pub rotation: f32,
+    /// Map cells per image pixel; below 1.0 each cell covers several pixels.
+    pub scale: f32,
+    /// Gray levels the output may use; the map dithers between the pair around each pixel.
+    pub levels: Levels,
+}
+
+impl Ordered {
+    pub fn new(map: ThresholdMap) -> Self {
+        Ordered {
+            map: Arc::new(map),
+            offset: (0, 0),
+            rotation: 0.0,
+            scale: 1.0,
+            levels: Levels::default(),
+        }
+    }
+
+    pub fn bayer(size: u32) -> Self {
+        Ordered::new(ThresholdMap::bayer(size))
+    }
+
+    pub fn blue_noise(size: u32, seed: u64) -> Self {
+        Ordered {
+            map: ThresholdMap::blue_noise(size, seed),
+            ..Ordered::default()
+        }
+    }
+}
+
+impl Default for Ordered {
+    fn default() -> Self {
+        Ordered::bayer(4)
+    }
+}
+
+impl Ordered {
+    // Threshold for pixel (x, y), sampling the map in its own rotated and scaled frame
+    fn threshold_at(&self, x: u32, y: u32, sin: f32, cos: f32) -> f32 {
+        let (ox, oy) = (self.offset.0 as i64, self.offset.1 as i64);
+        if self.rotation == 0.0 && self.scale == 1.0 {
+            return self.map.threshold(x as i64 + ox, y as i64 + oy);
+        }
+        let (fx, fy) = (x as f32 + 0.5, y as f32 + 0.5);
+        let u = ((fx * cos + fy * sin) * self.scale).floor() as i64;
+        let v = ((fy * cos - fx * sin) * self.scale).floor() as i64;
+        self.map.threshold(u + ox, v + oy)
+    }
+}
+
+impl Ditherer for Ordered {
+    fn dither(&self, img: &GrayImage) -> GrayImage {
+        let (width, height) = img.dimensions();
+        let mut dithered = GrayImage::new(width, height);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        for y in 0..height {
+            for x in 0..width {
+                let threshold = self.threshold_at(x, y, sin, cos);
+                let pixel = img.get_pixel(x, y)[0] as f32;
+                let new_val = self.levels.dither(pixel, threshold);
+                dithered.put_pixel(x, y, Luma([new_val]));
+            }
+        }
+
+        dithered
+    }
+}
+
+impl PaletteDitherer for Ordered {
+    fn dither_palette(&self, img: &RgbImage, palette: &Palette, metric: ColorMetric) -> RgbImage {
+        let (width, height) = img.dimensions();
+        let mut dithered = RgbImage::new(width, height);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let levels = &self.levels;
+        let spread = palette.spread_by(|c| levels.tone_of(c));
+        let mut matcher = Matcher::new(palette, metric);
+
+        for y in 0..height {
+            for x in 0..width {
+                // Nudge every channel by the map, scaled to the palette's spacing, then
+                // take the nearest colour; with black and white this is plain ordered dithering.
+                // The nudge is applied in the levels' tone space, linear light by default
+                let nudge = (0.5 - self.threshold_at(x, y, sin, cos) / 255.0) * spread;
+                let pixel = img.get_pixel(x, y).0.map(|v| levels.gray(levels.tone(v as f32) + nudge));
+                dithered.put_pixel(x, y, palette.colors()[matcher.nearest(pixel)]);
+            }
+        }
+
+        dithered
+    }
+}
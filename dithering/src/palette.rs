@@ -0,0 +1,301 @@
+//! Colour palettes for palette dithering.
+
+use image::{Rgb, RgbImage};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Most colours a palette may have.
+pub const MAX_PALETTE_SIZE: usize = 256;
+
+/// A fixed set of output colours.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Palette {
+    colors: Vec<Rgb<u8>>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Rgb<u8>>) -> Result<Self, PaletteError> {
+        match colors.len() {
+            0 | 1 => Err(PaletteError::TooFew),
+            n if n > MAX_PALETTE_SIZE => Err(PaletteError::TooMany(n)),
+            _ => Ok(Palette { colors }),
+        }
+    }
+
+    pub fn colors(&self) -> &[Rgb<u8>] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Always false; a palette has at least two colours.
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Typical gap between neighbouring colours, per channel: the mean over colours of the
+    /// largest channel difference to the nearest neighbour. Ordered dithering spreads its
+    /// thresholds across this range, so black and white, or the eight RGB corners, get 0..255.
+    pub fn spread(&self) -> f32 {
+        self.spread_by(|c| c as f32)
+    }
+
+    /// [`Palette::spread`] with each channel value first mapped through `channel`, such as into linear light.
+    pub fn spread_by(&self, channel: impl Fn(u8) -> f32) -> f32 {
+        let total: f32 = self
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                self.colors
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, b)| (0..3).map(|c| (channel(a[c]) - channel(b[c])).abs()).fold(0.0, f32::max))
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .sum();
+        total / self.colors.len() as f32
+    }
+
+    /// A palette from the built-in catalogue, by name, ignoring case.
+    pub fn named(name: &str) -> Option<Self> {
+        let wanted = name.trim().to_ascii_lowercase();
+        named::ALL
+            .iter()
+            .find(|palette| palette.name == wanted)
+            .map(|palette| Palette { colors: palette.colors() })
+    }
+
+    /// How many pixels of `img` use each colour, in palette order.
+    pub fn usage(&self, img: &RgbImage) -> Vec<u64> {
+        let mut index = HashMap::with_capacity(self.colors.len());
+        for (i, color) in self.colors.iter().enumerate().rev() {
+            index.insert(color.0, i);
+        }
+        let mut counts = vec![0; self.colors.len()];
+        for pixel in img.pixels() {
+            if let Some(&i) = index.get(&pixel.0) {
+                counts[i] += 1;
+            }
+        }
+        counts
+    }
+
+    /// The colours as `#rrggbb` strings.
+    pub fn to_hex(&self) -> Vec<String> {
+        self.colors.iter().map(|c| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])).collect()
+    }
+}
+
+/// A palette as given in a request: the name of a built-in palette, or a list of
+/// hex colours, `#rrggbb` or `#rgb`, `#` optional.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PaletteSpec {
+    Named(String),
+    Colors(Vec<String>),
+}
+
+impl PaletteSpec {
+    pub fn build(&self) -> Result<Palette, PaletteError> {
+        match self {
+            PaletteSpec::Named(name) => Palette::named(name).ok_or_else(|| PaletteError::UnknownName(name.clone())),
+            PaletteSpec::Colors(hexes) if hexes.len() > MAX_PALETTE_SIZE => Err(PaletteError::TooMany(hexes.len())),
+            PaletteSpec::Colors(hexes) => {
+                let colors = hexes.iter().map(|hex| parse_hex(hex)).collect::<Result<_, _>>()?;
+                Palette::new(colors)
+            }
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Rgb<u8>, PaletteError> {
+    let bad = || PaletteError::BadColor(hex.to_string());
+    let digits = hex.trim().trim_start_matches('#');
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(bad());
+    }
+    let channel = |s: &str| u8::from_str_radix(s, 16).map_err(|_| bad());
+    match digits.len() {
+        6 => Ok(Rgb([channel(&digits[0..2])?, channel(&digits[2..4])?, channel(&digits[4..6])?])),
+        // #rgb doubles each digit: #f80 is #ff8800
+        3 => {
+            let short = |i: usize| channel(&digits[i..i + 1]).map(|v| v * 17);
+            Ok(Rgb([short(0)?, short(1)?, short(2)?]))
+        }
+        _ => Err(bad()),
+    }
+}
+
+/// Why a palette was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteError {
+    TooFew,
+    TooMany(usize),
+    BadColor(String),
+    UnknownName(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::TooFew => f.write_str("need at least two colours"),
+            PaletteError::TooMany(count) => write!(f, "{} colours given, at most {} allowed", count, MAX_PALETTE_SIZE),
+            PaletteError::BadColor(given) => write!(f, "`{}` is not a #rrggbb or #rgb colour", given),
+            PaletteError::UnknownName(given) => {
+                let valid: Vec<&str> = named::ALL.iter().map(|palette| palette.name).collect();
+                write!(f, "unknown palette `{}`, expected one of: {}", given, valid.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// Built-in palettes from classic hardware and common display panels.
+pub mod named {
+    use image::Rgb;
+
+    /// A catalogue palette: its request name, what it is, and its colours as `0xRRGGBB`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct NamedPalette {
+        pub name: &'static str,
+        pub description: &'static str,
+        pub colors: &'static [u32],
+    }
+
+    impl NamedPalette {
+        pub fn colors(&self) -> Vec<Rgb<u8>> {
+            self.colors
+                .iter()
+                .map(|&rgb| Rgb([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]))
+                .collect()
+        }
+    }
+
+    const fn named(name: &'static str, description: &'static str, colors: &'static [u32]) -> NamedPalette {
+        NamedPalette { name, description, colors }
+    }
+
+    pub const CGA_0_LOW: NamedPalette = named(
+        "cga-0-low",
+        "CGA mode 4, palette 0, low intensity: green, red, brown",
+        &[0x000000, 0x00AA00, 0xAA0000, 0xAA5500],
+    );
+    pub const CGA_0_HIGH: NamedPalette = named(
+        "cga-0-high",
+        "CGA mode 4, palette 0, high intensity: light green, light red, yellow",
+        &[0x000000, 0x55FF55, 0xFF5555, 0xFFFF55],
+    );
+    pub const CGA_1_LOW: NamedPalette = named(
+        "cga-1-low",
+        "CGA mode 4, palette 1, low intensity: cyan, magenta, light gray",
+        &[0x000000, 0x00AAAA, 0xAA00AA, 0xAAAAAA],
+    );
+    pub const CGA_1_HIGH: NamedPalette = named(
+        "cga-1-high",
+        "CGA mode 4, palette 1, high intensity: light cyan, light magenta, white",
+        &[0x000000, 0x55FFFF, 0xFF55FF, 0xFFFFFF],
+    );
+    pub const CGA_5_LOW: NamedPalette = named(
+        "cga-5-low",
+        "CGA mode 5, low intensity: cyan, red, light gray",
+        &[0x000000, 0x00AAAA, 0xAA0000, 0xAAAAAA],
+    );
+    pub const CGA_5_HIGH: NamedPalette = named(
+        "cga-5-high",
+        "CGA mode 5, high intensity: light cyan, light red, white",
+        &[0x000000, 0x55FFFF, 0xFF5555, 0xFFFFFF],
+    );
+    pub const EGA: NamedPalette = named(
+        "ega",
+        "IBM EGA default 16 colours",
+        &[
+            0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA,
+            0x555555, 0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+        ],
+    );
+    pub const GAME_BOY: NamedPalette = named(
+        "gameboy",
+        "Nintendo Game Boy (DMG) four greens",
+        &[0x0F380F, 0x306230, 0x8BAC0F, 0x9BBC0F],
+    );
+    pub const PICO_8: NamedPalette = named(
+        "pico-8",
+        "PICO-8 fantasy console",
+        &[
+            0x000000, 0x1D2B53, 0x7E2553, 0x008751, 0xAB5236, 0x5F574F, 0xC2C3C7, 0xFFF1E8,
+            0xFF004D, 0xFFA300, 0xFFEC27, 0x00E436, 0x29ADFF, 0x83769C, 0xFF77A8, 0xFFCCAA,
+        ],
+    );
+    pub const C64: NamedPalette = named(
+        "c64",
+        "Commodore 64 (Pepto's measured colours)",
+        &[
+            0x000000, 0xFFFFFF, 0x68372B, 0x70A4B2, 0x6F3D86, 0x588D43, 0x352879, 0xB8C76F,
+            0x6F4F25, 0x433900, 0x9A6759, 0x444444, 0x6C6C6C, 0x9AD284, 0x6C5EB5, 0x959595,
+        ],
+    );
+    pub const NES: NamedPalette = named(
+        "nes",
+        "Nintendo Entertainment System 2C02, distinct colours only",
+        &[
+            0x7C7C7C, 0x0000FC, 0x0000BC, 0x4428BC, 0x940084, 0xA80020, 0xA81000, 0x881400,
+            0x503000, 0x007800, 0x006800, 0x005800, 0x004058, 0x000000, 0xBCBCBC, 0x0078F8,
+            0x0058F8, 0x6844FC, 0xD800CC, 0xE40058, 0xF83800, 0xE45C10, 0xAC7C00, 0x00B800,
+            0x00A800, 0x00A844, 0x008888, 0xF8F8F8, 0x3CBCFC, 0x6888FC, 0x9878F8, 0xF878F8,
+            0xF85898, 0xF87858, 0xFCA044, 0xF8B800, 0xB8F818, 0x58D854, 0x58F898, 0x00E8D8,
+            0x787878, 0xFCFCFC, 0xA4E4FC, 0xB8B8F8, 0xD8B8F8, 0xF8B8F8, 0xF8A4C0, 0xF0D0B0,
+            0xFCE0A8, 0xF8D878, 0xD8F878, 0xB8F8B8, 0xB8F8D8, 0x00FCFC, 0xF8D8F8,
+        ],
+    );
+    pub const ZX_SPECTRUM: NamedPalette = named(
+        "zx-spectrum",
+        "Sinclair ZX Spectrum, normal and bright",
+        &[
+            0x000000, 0x0000D7, 0xD70000, 0xD700D7, 0x00D700, 0x00D7D7, 0xD7D700, 0xD7D7D7,
+            0x0000FF, 0xFF0000, 0xFF00FF, 0x00FF00, 0x00FFFF, 0xFFFF00, 0xFFFFFF,
+        ],
+    );
+    pub const APPLE_II: NamedPalette = named(
+        "apple-ii",
+        "Apple II low-resolution colours; the two identical grays appear once",
+        &[
+            0x000000, 0x722640, 0x40337F, 0xE434FE, 0x0E5940, 0x808080, 0x1B9AFE, 0xBFB3FF,
+            0x404C00, 0xE46501, 0xF1A6BF, 0x1BCB01, 0xBFCC80, 0x8DD9BF, 0xFFFFFF,
+        ],
+    );
+    pub const MAC_16: NamedPalette = named(
+        "mac-16",
+        "Classic Macintosh 16-colour system palette",
+        &[
+            0xFFFFFF, 0xFCF305, 0xFF6403, 0xDD0806, 0xF20884, 0x4700A5, 0x0000D3, 0x02ABEA,
+            0x1FB714, 0x006411, 0x562C05, 0x90713A, 0xC0C0C0, 0x808080, 0x404040, 0x000000,
+        ],
+    );
+    pub const EINK_BWR: NamedPalette = named(
+        "eink-bwr",
+        "Three-colour e-ink panel: black, white, red",
+        &[0x000000, 0xFFFFFF, 0xFF0000],
+    );
+    pub const EINK_BWY: NamedPalette = named(
+        "eink-bwy",
+        "Three-colour e-ink panel: black, white, yellow",
+        &[0x000000, 0xFFFFFF, 0xFFFF00],
+    );
+    pub const EINK_7: NamedPalette = named(
+        "eink-7",
+        "Seven-colour ACeP e-ink panel: black, white, green, blue, red, yellow, orange",
+        &[0x000000, 0xFFFFFF, 0x00FF00, 0x0000FF, 0xFF0000, 0xFFFF00, 0xFF8000],
+    );
+
+    /// The whole catalogue, in listing order.
+    pub const ALL: [NamedPalette; 17] = [
+        CGA_0_LOW, CGA_0_HIGH, CGA_1_LOW, CGA_1_HIGH, CGA_5_LOW, CGA_5_HIGH, EGA, GAME_BOY, PICO_8,
+        C64, NES, ZX_SPECTRUM, APPLE_II, MAC_16, EINK_BWR, EINK_BWY, EINK_7,
+    ];
+}
@@ -0,0 +1,98 @@
+//! A validated request ready to run: colour handling around the chosen ditherer.
+
+use image::{DynamicImage, GrayImage, RgbImage};
+
+use crate::cmyk::{CmykHalftone, CmykOutput};
+use crate::grayscale::Grayscale;
+use crate::metric::ColorMetric;
+use crate::palette::Palette;
+use crate::preprocess::Preprocess;
+use crate::quantize::Quantizer;
+use crate::{Ditherer, PaletteDitherer};
+
+/// The result of running a [`Pipeline`].
+pub enum Rendered {
+    Gray(GrayImage),
+    Rgb(RgbImage),
+    /// An image using only the colours of `palette`.
+    Paletted { image: RgbImage, palette: Palette },
+    /// Screened CMYK plates in [`crate::cmyk::INKS`] order.
+    Separations([GrayImage; 4]),
+}
+
+impl Rendered {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Rendered::Gray(img) => img.dimensions(),
+            Rendered::Rgb(img) | Rendered::Paletted { image: img, .. } => img.dimensions(),
+            Rendered::Separations([plate, ..]) => plate.dimensions(),
+        }
+    }
+}
+
+/// Everything between the decoded upload and the encoded result; built by
+/// [`crate::Algorithm::pipeline`].
+pub struct Pipeline {
+    stage: Stage,
+}
+
+/// Where palette dithering gets its colours.
+pub(crate) enum PaletteSource {
+    Fixed(Palette),
+    Extracted { quantizer: Quantizer, colors: usize, seed: u64 },
+}
+
+enum Stage {
+    Gray {
+        ditherer: Box<dyn Ditherer>,
+        grayscale: Grayscale,
+        preprocess: Preprocess,
+    },
+    Palette {
+        ditherer: Box<dyn PaletteDitherer>,
+        source: PaletteSource,
+        metric: ColorMetric,
+    },
+    Cmyk { screen: CmykHalftone, output: CmykOutput },
+}
+
+impl Pipeline {
+    pub(crate) fn gray(ditherer: Box<dyn Ditherer>, grayscale: Grayscale, preprocess: Preprocess) -> Self {
+        Pipeline { stage: Stage::Gray { ditherer, grayscale, preprocess } }
+    }
+
+    pub(crate) fn paletted(ditherer: Box<dyn PaletteDitherer>, source: PaletteSource, metric: ColorMetric) -> Self {
+        Pipeline { stage: Stage::Palette { ditherer, source, metric } }
+    }
+
+    pub(crate) fn cmyk(screen: CmykHalftone, output: CmykOutput) -> Self {
+        Pipeline { stage: Stage::Cmyk { screen, output } }
+    }
+
+    pub fn run(&self, img: &DynamicImage) -> Rendered {
+        match &self.stage {
+            Stage::Gray { ditherer, grayscale, preprocess } => {
+                let gray = preprocess.apply(grayscale.convert(img));
+                Rendered::Gray(ditherer.dither(&gray))
+            }
+            Stage::Palette { ditherer, source, metric } => {
+                let rgb = img.to_rgb8();
+                let palette = match source {
+                    PaletteSource::Fixed(palette) => palette.clone(),
+                    PaletteSource::Extracted { quantizer, colors, seed } => quantizer.extract(&rgb, *colors, *seed),
+                };
+                Rendered::Paletted {
+                    image: ditherer.dither_palette(&rgb, &palette, *metric),
+                    palette,
+                }
+            }
+            Stage::Cmyk { screen, output } => {
+                let plates = screen.plates(&img.to_rgb8());
+                match output {
+                    CmykOutput::Composite => Rendered::Rgb(CmykHalftone::composite(&plates)),
+                    CmykOutput::Separations => Rendered::Separations(plates),
+                }
+            }
+        }
+    }
+}
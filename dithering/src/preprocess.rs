@@ -0,0 +1,408 @@
+//! Tonal adjustments applied to the gray image before it is dithered.
+
+use image::{GrayImage, Luma};
+use serde::Deserialize;
+use std::fmt;
+
+/// Most control points a tone curve may have.
+pub const MAX_CURVE_POINTS: usize = 64;
+
+/// Histogram equalisation, spreading the grays an image uses across the full range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Equalize {
+    /// One mapping for the whole image.
+    Global,
+    /// Contrast-limited adaptive histogram equalisation: a mapping per tile of
+    /// `tile_size` pixels square, blended between tile centres. No gray may take more
+    /// than `clip_limit` times its even share of a tile, which keeps flat areas from
+    /// having their noise blown up.
+    Clahe { tile_size: u32, clip_limit: f32 },
+}
+
+/// Which equalisation a request asked for; CLAHE takes its settings from separate fields.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EqualizeMode {
+    Global,
+    Clahe,
+}
+
+impl Equalize {
+    pub fn apply(self, img: &GrayImage) -> GrayImage {
+        match self {
+            Equalize::Global => {
+                let mut histogram = [0u64; 256];
+                for pixel in img.pixels() {
+                    histogram[pixel[0] as usize] += 1;
+                }
+                let lut = equalize_histogram(&histogram);
+                let mut equalized = img.clone();
+                for pixel in equalized.pixels_mut() {
+                    pixel[0] = lut[pixel[0] as usize];
+                }
+                equalized
+            }
+            Equalize::Clahe { tile_size, clip_limit } => clahe(img, tile_size.max(1), clip_limit),
+        }
+    }
+}
+
+// Mapping that turns the cumulative histogram into a straight ramp; the darkest gray
+// present maps to black. An image of a single gray is left alone.
+fn equalize_histogram(histogram: &[u64; 256]) -> [u8; 256] {
+    let total: u64 = histogram.iter().sum();
+    let first = histogram.iter().copied().find(|&count| count > 0).unwrap_or(0);
+    if total == first {
+        return std::array::from_fn(|gray| gray as u8);
+    }
+    let mut cumulative = 0;
+    std::array::from_fn(|gray| {
+        cumulative += histogram[gray];
+        let share = cumulative.saturating_sub(first) as f64 / (total - first) as f64;
+        (share * 255.0).round() as u8
+    })
+}
+
+fn clahe(img: &GrayImage, tile_size: u32, clip_limit: f32) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let (columns, rows) = (width.div_ceil(tile_size).max(1), height.div_ceil(tile_size).max(1));
+    let luts: Vec<[u8; 256]> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let mut histogram = [0u64; 256];
+            let (x0, y0) = (column * tile_size, row * tile_size);
+            for y in y0..(y0 + tile_size).min(height) {
+                for x in x0..(x0 + tile_size).min(width) {
+                    histogram[img.get_pixel(x, y)[0] as usize] += 1;
+                }
+            }
+            // A tile of a single gray is left alone, as global equalisation leaves a flat image
+            let total: u64 = histogram.iter().sum();
+            if histogram.contains(&total) {
+                return std::array::from_fn(|gray| gray as u8);
+            }
+            clip_histogram(&mut histogram, clip_limit);
+            // Unlike global equalisation the darkest gray is not pinned to black: clipped
+            // tiles are meant to stay close to their original tones
+            let mut cumulative = 0;
+            std::array::from_fn(|gray| {
+                cumulative += histogram[gray];
+                (cumulative as f64 * 255.0 / total as f64).round() as u8
+            })
+        })
+        .collect();
+
+    // Each pixel blends the mappings of the four tile centres around it; past the
+    // outermost centres the nearest ones are used alone
+    let position = |coordinate: u32, tiles: u32| {
+        let t = ((coordinate as f32 + 0.5) / tile_size as f32 - 0.5).clamp(0.0, (tiles - 1) as f32);
+        let low = (t.floor() as u32).min(tiles - 1);
+        (low, (low + 1).min(tiles - 1), t - low as f32)
+    };
+    GrayImage::from_fn(width, height, |x, y| {
+        let (left, right, fx) = position(x, columns);
+        let (top, bottom, fy) = position(y, rows);
+        let gray = img.get_pixel(x, y)[0] as usize;
+        let at = |column: u32, row: u32| luts[(row * columns + column) as usize][gray] as f32;
+        let upper = at(left, top) * (1.0 - fx) + at(right, top) * fx;
+        let lower = at(left, bottom) * (1.0 - fx) + at(right, bottom) * fx;
+        Luma([(upper * (1.0 - fy) + lower * fy).round() as u8])
+    })
+}
+
+// Cap every bin at `clip_limit` times the mean and share what was cut off evenly,
+// the last few counts going to bins spaced across the whole range
+fn clip_histogram(histogram: &mut [u64; 256], clip_limit: f32) {
+    let total: u64 = histogram.iter().sum();
+    let limit = ((clip_limit as f64 * total as f64 / 256.0) as u64).max(1);
+    let excess: u64 = histogram.iter().map(|&count| count.saturating_sub(limit)).sum();
+    let (share, remainder) = (excess / 256, (excess % 256) as usize);
+    let step = 256usize.checked_div(remainder).unwrap_or(usize::MAX);
+    for (gray, count) in histogram.iter_mut().enumerate() {
+        let extra = gray % step == 0 && gray / step < remainder;
+        *count = (*count).min(limit) + share + extra as u64;
+    }
+}
+
+/// Sharpening, to win back the fine detail error diffusion smears.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sharpen {
+    /// Add back `amount` times the difference from a Gaussian blur of standard deviation
+    /// `radius`, wherever that difference is at least `threshold` grays; the threshold
+    /// keeps smooth areas and film grain from being sharpened.
+    Unsharp { radius: f32, amount: f32, threshold: u8 },
+    /// Subtract `amount` times the 4-neighbour Laplacian: crisp, single-pixel edges.
+    Laplacian { amount: f32 },
+}
+
+/// Which sharpening a request asked for; the settings come from separate fields.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum SharpenMode {
+    Unsharp,
+    Laplacian,
+}
+
+impl Sharpen {
+    pub fn apply(self, img: &GrayImage) -> GrayImage {
+        let (width, height) = img.dimensions();
+        let source: Vec<f32> = img.pixels().map(|p| p[0] as f32).collect();
+        let at = |x: i64, y: i64| {
+            let (x, y) = (x.clamp(0, width as i64 - 1), y.clamp(0, height as i64 - 1));
+            source[(y * width as i64 + x) as usize]
+        };
+        match self {
+            Sharpen::Unsharp { radius, amount, threshold } => {
+                let blurred = gaussian_blur(&source, width, height, radius);
+                GrayImage::from_fn(width, height, |x, y| {
+                    let i = (y * width + x) as usize;
+                    let detail = source[i] - blurred[i];
+                    let sharpened = if detail.abs() >= threshold as f32 { source[i] + amount * detail } else { source[i] };
+                    Luma([sharpened.round().clamp(0.0, 255.0) as u8])
+                })
+            }
+            Sharpen::Laplacian { amount } => GrayImage::from_fn(width, height, |x, y| {
+                let (x, y) = (x as i64, y as i64);
+                let laplacian = at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+                Luma([(at(x, y) - amount * laplacian).round().clamp(0.0, 255.0) as u8])
+            }),
+        }
+    }
+}
+
+// Separable Gaussian blur of a row-major buffer, extending edge pixels outwards
+fn gaussian_blur(source: &[f32], width: u32, height: u32, sigma: f32) -> Vec<f32> {
+    let reach = (3.0 * sigma).ceil().max(1.0) as i64;
+    let weights: Vec<f32> = (-reach..=reach).map(|d| (-(d * d) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = weights.iter().sum();
+    let weights: Vec<f32> = weights.iter().map(|w| w / total).collect();
+    let (w, h) = (width as i64, height as i64);
+    let pass = |input: &[f32], horizontal: bool| -> Vec<f32> {
+        (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                (-reach..=reach)
+                    .zip(&weights)
+                    .map(|(d, weight)| {
+                        let (sx, sy) = if horizontal { ((x + d).clamp(0, w - 1), y) } else { (x, (y + d).clamp(0, h - 1)) };
+                        input[(sy * w + sx) as usize] * weight
+                    })
+                    .sum()
+            })
+            .collect()
+    };
+    pass(&pass(source, true), false)
+}
+
+/// Equalisation, then levels, gamma, brightness and contrast, a tone curve, and
+/// sharpening, in that order. The defaults leave the image unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preprocess {
+    pub equalize: Option<Equalize>,
+    /// Input gray that becomes black; darker grays clip.
+    pub black_point: u8,
+    /// Input gray that becomes white; lighter grays clip.
+    pub white_point: u8,
+    /// Gray black is mapped to after levels; above `output_white` inverts the image.
+    pub output_black: u8,
+    pub output_white: u8,
+    /// Midtone gamma; above 1 lightens, below 1 darkens.
+    pub gamma: f32,
+    /// Added to every gray, -1.0 to 1.0 of full scale.
+    pub brightness: f32,
+    /// -1.0 flattens to mid-gray, 0.0 leaves alone, 1.0 is a hard threshold at mid-gray.
+    pub contrast: f32,
+    pub curve: Option<ToneCurve>,
+    pub sharpen: Option<Sharpen>,
+}
+
+impl Default for Preprocess {
+    fn default() -> Self {
+        Preprocess {
+            equalize: None,
+            black_point: 0,
+            white_point: 255,
+            output_black: 0,
+            output_white: 255,
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 0.0,
+            curve: None,
+            sharpen: None,
+        }
+    }
+}
+
+impl Preprocess {
+    /// The adjustments after equalisation as a table from input gray to output gray.
+    pub fn lut(&self) -> [u8; 256] {
+        let range = (self.white_point as f32 - self.black_point as f32).max(1.0);
+        let (out_black, out_white) = (self.output_black as f32 / 255.0, self.output_white as f32 / 255.0);
+        let slope = if self.contrast > 0.0 {
+            1.0 / (1.0 - self.contrast).max(1.0 / 256.0)
+        } else {
+            1.0 + self.contrast
+        };
+        std::array::from_fn(|gray| {
+            let v = ((gray as f32 - self.black_point as f32) / range).clamp(0.0, 1.0);
+            let v = out_black + v.powf(1.0 / self.gamma) * (out_white - out_black);
+            let v = ((v + self.brightness - 0.5) * slope + 0.5).clamp(0.0, 1.0);
+            let v = v * 255.0;
+            let v = self.curve.as_ref().map_or(v, |curve| curve.eval(v));
+            v.round().clamp(0.0, 255.0) as u8
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Preprocess::default()
+    }
+
+    pub fn apply(&self, mut img: GrayImage) -> GrayImage {
+        if self.is_identity() {
+            return img;
+        }
+        if let Some(equalize) = self.equalize {
+            img = equalize.apply(&img);
+        }
+        let lut = self.lut();
+        for pixel in img.pixels_mut() {
+            pixel[0] = lut[pixel[0] as usize];
+        }
+        match self.sharpen {
+            Some(sharpen) => sharpen.apply(&img),
+            None => img,
+        }
+    }
+}
+
+/// A tone curve through control points, interpolated with a monotone cubic
+/// (Fritsch–Carlson) so it never overshoots between points. Inputs outside the
+/// first and last points take their outputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneCurve {
+    points: Vec<[f32; 2]>,
+    // Tangent at each point
+    slopes: Vec<f32>,
+}
+
+impl ToneCurve {
+    /// A curve through `points`, each `[input, output]` from 0.0 to 255.0, with inputs strictly increasing.
+    pub fn new(points: Vec<[f32; 2]>) -> Result<Self, CurveError> {
+        if points.len() < 2 {
+            return Err(CurveError::TooFew);
+        }
+        if points.len() > MAX_CURVE_POINTS {
+            return Err(CurveError::TooMany(points.len()));
+        }
+        if points.iter().flatten().any(|v| !(0.0..=255.0).contains(v)) {
+            return Err(CurveError::OutOfRange);
+        }
+        if points.windows(2).any(|pair| pair[1][0] <= pair[0][0]) {
+            return Err(CurveError::NotIncreasing);
+        }
+
+        let secants: Vec<f32> = points
+            .windows(2)
+            .map(|pair| (pair[1][1] - pair[0][1]) / (pair[1][0] - pair[0][0]))
+            .collect();
+        let mut slopes = Vec::with_capacity(points.len());
+        slopes.push(secants[0]);
+        for pair in secants.windows(2) {
+            // Flat where the curve turns, so it cannot overshoot a peak or trough
+            slopes.push(if pair[0] * pair[1] <= 0.0 { 0.0 } else { (pair[0] + pair[1]) / 2.0 });
+        }
+        slopes.push(secants[secants.len() - 1]);
+        for (i, &secant) in secants.iter().enumerate() {
+            if secant == 0.0 {
+                slopes[i] = 0.0;
+                slopes[i + 1] = 0.0;
+                continue;
+            }
+            // Scale tangents back into the region that keeps this segment monotone
+            let (a, b) = (slopes[i] / secant, slopes[i + 1] / secant);
+            let length = a.hypot(b);
+            if length > 3.0 {
+                slopes[i] = 3.0 * a / length * secant;
+                slopes[i + 1] = 3.0 * b / length * secant;
+            }
+        }
+        Ok(ToneCurve { points, slopes })
+    }
+
+    pub fn points(&self) -> &[[f32; 2]] {
+        &self.points
+    }
+
+    /// Output of the curve at `x`.
+    pub fn eval(&self, x: f32) -> f32 {
+        let last = self.points.len() - 1;
+        if x <= self.points[0][0] {
+            return self.points[0][1];
+        }
+        if x >= self.points[last][0] {
+            return self.points[last][1];
+        }
+        let i = self.points.partition_point(|point| point[0] <= x) - 1;
+        let ([x0, y0], [x1, y1]) = (self.points[i], self.points[i + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        // Cubic Hermite basis
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.slopes[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.slopes[i + 1]
+    }
+}
+
+/// A tone curve as given in a request: `[[input, output], ...]` control points.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct ToneCurveSpec(pub Vec<[f32; 2]>);
+
+impl ToneCurveSpec {
+    pub fn build(&self) -> Result<ToneCurve, CurveError> {
+        ToneCurve::new(self.0.clone())
+    }
+}
+
+/// Why a tone curve was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CurveError {
+    TooFew,
+    TooMany(usize),
+    OutOfRange,
+    NotIncreasing,
+}
+
+impl fmt::Display for CurveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurveError::TooFew => f.write_str("need at least two control points"),
+            CurveError::TooMany(count) => write!(f, "{} control points given, at most {} allowed", count, MAX_CURVE_POINTS),
+            CurveError::OutOfRange => f.write_str("control points must be between 0 and 255"),
+            CurveError::NotIncreasing => f.write_str("control point inputs must be strictly increasing"),
+        }
+    }
+}
+
+impl std::error::Error for CurveError {}
+
+#[cfg(test)]
+mod tests {
+    use super::clip_histogram;
+
+    #[test]
+    fn clipping_keeps_the_histogram_total() {
+        let spiky: [u64; 256] = std::array::from_fn(|gray| if gray % 17 == 0 { 1000 } else { gray as u64 % 3 });
+        let single: [u64; 256] = std::array::from_fn(|gray| if gray == 90 { 4096 } else { 0 });
+        for histogram in [spiky, single] {
+            let total: u64 = histogram.iter().sum();
+            for clip_limit in [1.0, 1.5, 2.0, 4.0, 64.0] {
+                let mut clipped = histogram;
+                clip_histogram(&mut clipped, clip_limit);
+                assert_eq!(clipped.iter().sum::<u64>(), total, "clip limit {}", clip_limit);
+            }
+        }
+    }
+}
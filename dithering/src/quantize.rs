@@ -0,0 +1,287 @@
+//! Palette extraction: reduce an image's colours to a target count.
+
+use image::{Rgb, RgbImage};
+use serde::Deserialize;
+
+use crate::palette::Palette;
+use crate::blue_noise::splitmix64;
+
+// Bits kept per channel when histogramming; 15-bit colour is plenty to pick a palette from
+const HISTOGRAM_BITS: u32 = 5;
+// Lloyd iterations before k-means gives up on converging
+const KMEANS_ITERATIONS: usize = 16;
+
+/// How to derive a palette from an image.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quantizer {
+    /// Heckbert's median cut: split the widest box of colours at its median until there are enough.
+    MedianCut,
+    /// k-means clustering from k-means++ starting centres; deterministic for a given seed.
+    KMeans,
+    /// Octree reduction: merge the least-used leaves of a colour octree, deepest first.
+    Octree,
+}
+
+impl Quantizer {
+    pub const ALL: [Quantizer; 3] = [Quantizer::MedianCut, Quantizer::KMeans, Quantizer::Octree];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantizer::MedianCut => "median-cut",
+            Quantizer::KMeans => "k-means",
+            Quantizer::Octree => "octree",
+        }
+    }
+
+    /// A palette of at most `colors` entries for `img`; fewer if the image has fewer colours.
+    /// `seed` only affects k-means.
+    pub fn extract(self, img: &RgbImage, colors: usize, seed: u64) -> Palette {
+        let bins = histogram(img);
+        let mut extracted = match self {
+            Quantizer::MedianCut => median_cut(bins, colors),
+            Quantizer::KMeans => k_means(&bins, colors, seed),
+            Quantizer::Octree => octree(&bins, colors),
+        };
+        // A flat image yields a single colour; pad with black or white, whichever is further
+        if extracted.len() < 2 {
+            let only = extracted.first().copied().unwrap_or(Rgb([0, 0, 0]));
+            let luma: u32 = only.0.iter().map(|&c| c as u32).sum();
+            extracted.push(if luma > 3 * 127 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) });
+        }
+        extracted.truncate(colors.max(2));
+        Palette::new(extracted).expect("extracted palette has between 2 and 256 colours")
+    }
+}
+
+// Pixels falling into one histogram cell, with their exact mean
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+    color: [f32; 3],
+    count: u64,
+}
+
+fn histogram(img: &RgbImage) -> Vec<Bin> {
+    let shift = 8 - HISTOGRAM_BITS;
+    let mut cells = vec![([0u64; 3], 0u64); 1 << (3 * HISTOGRAM_BITS)];
+    for pixel in img.pixels() {
+        let [r, g, b] = pixel.0.map(|c| (c >> shift) as usize);
+        let (sum, count) = &mut cells[(r << (2 * HISTOGRAM_BITS)) | (g << HISTOGRAM_BITS) | b];
+        for (total, &c) in sum.iter_mut().zip(&pixel.0) {
+            *total += c as u64;
+        }
+        *count += 1;
+    }
+    cells
+        .into_iter()
+        .filter(|&(_, count)| count > 0)
+        .map(|(sum, count)| Bin {
+            color: sum.map(|total| total as f32 / count as f32),
+            count,
+        })
+        .collect()
+}
+
+// Pixel-weighted mean colour of some bins
+fn mean(bins: &[Bin]) -> Rgb<u8> {
+    let total: u64 = bins.iter().map(|bin| bin.count).sum();
+    Rgb(std::array::from_fn(|c| {
+        let sum: f64 = bins.iter().map(|bin| bin.color[c] as f64 * bin.count as f64).sum();
+        (sum / total as f64).round() as u8
+    }))
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]).powi(2)).sum()
+}
+
+fn median_cut(bins: Vec<Bin>, colors: usize) -> Vec<Rgb<u8>> {
+    let mut boxes = vec![bins];
+    while boxes.len() < colors {
+        // The box with the widest channel range; single-colour boxes cannot split
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, bins)| bins.len() > 1)
+            .flat_map(|(i, bins)| {
+                (0..3).map(move |c| {
+                    let (lo, hi) = bins.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), bin| {
+                        (lo.min(bin.color[c]), hi.max(bin.color[c]))
+                    });
+                    (i, c, hi - lo)
+                })
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((i, channel, _)) = widest else { break };
+
+        let bins = &mut boxes[i];
+        bins.sort_by(|a, b| a.color[channel].total_cmp(&b.color[channel]));
+        let total: u64 = bins.iter().map(|bin| bin.count).sum();
+        let mut seen = 0;
+        let median = bins
+            .iter()
+            .position(|bin| {
+                seen += bin.count;
+                seen * 2 >= total
+            })
+            .unwrap_or(0);
+        // Keep at least one bin on each side
+        let split = (median + 1).clamp(1, bins.len() - 1);
+        let upper = bins.split_off(split);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|bins| mean(bins)).collect()
+}
+
+fn k_means(bins: &[Bin], colors: usize, seed: u64) -> Vec<Rgb<u8>> {
+    if bins.len() <= colors {
+        return bins.iter().map(|bin| mean(std::slice::from_ref(bin))).collect();
+    }
+    let mut state = seed;
+    let mut random = || (splitmix64(&mut state) >> 11) as f64 / (1u64 << 53) as f64;
+
+    // k-means++: each new centre is drawn with probability proportional to
+    // pixels times squared distance from the nearest centre so far
+    let mut centres: Vec<[f32; 3]> = Vec::with_capacity(colors);
+    let mut nearest = vec![f32::INFINITY; bins.len()];
+    while centres.len() < colors {
+        let weights: Vec<f64> = bins
+            .iter()
+            .zip(&nearest)
+            .map(|(bin, &d)| bin.count as f64 * if centres.is_empty() { 1.0 } else { d as f64 })
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut target = random() * total;
+        let pick = weights
+            .iter()
+            .position(|&w| {
+                target -= w;
+                target < 0.0
+            })
+            .unwrap_or(bins.len() - 1);
+        let centre = bins[pick].color;
+        for (d, bin) in nearest.iter_mut().zip(bins) {
+            *d = d.min(distance(bin.color, centre));
+        }
+        centres.push(centre);
+    }
+
+    let mut assignment = vec![usize::MAX; bins.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut changed = false;
+        for (slot, bin) in assignment.iter_mut().zip(bins) {
+            let closest = (0..centres.len())
+                .min_by(|&a, &b| distance(bin.color, centres[a]).total_cmp(&distance(bin.color, centres[b])))
+                .unwrap_or(0);
+            changed |= *slot != closest;
+            *slot = closest;
+        }
+        if !changed {
+            break;
+        }
+        let mut sums = vec![([0f64; 3], 0u64); centres.len()];
+        for (&cluster, bin) in assignment.iter().zip(bins) {
+            let (sum, count) = &mut sums[cluster];
+            for (total, &c) in sum.iter_mut().zip(&bin.color) {
+                *total += c as f64 * bin.count as f64;
+            }
+            *count += bin.count;
+        }
+        for (centre, (sum, count)) in centres.iter_mut().zip(sums) {
+            // An emptied cluster keeps its old centre
+            if count > 0 {
+                *centre = sum.map(|s| (s / count as f64) as f32);
+            }
+        }
+    }
+    centres.iter().map(|centre| Rgb(centre.map(|c| c.round().clamp(0.0, 255.0) as u8))).collect()
+}
+
+// Levels below the root; one per bit of an 8-bit channel
+const OCTREE_DEPTH: usize = 8;
+
+struct OctreeNode {
+    sum: [f64; 3],
+    count: u64,
+    children: [Option<usize>; 8],
+}
+
+impl OctreeNode {
+    fn add(&mut self, bin: &Bin) {
+        for (total, &c) in self.sum.iter_mut().zip(&bin.color) {
+            *total += c as f64 * bin.count as f64;
+        }
+        self.count += bin.count;
+    }
+}
+
+fn octree(bins: &[Bin], colors: usize) -> Vec<Rgb<u8>> {
+    let mut nodes = vec![OctreeNode { sum: [0.0; 3], count: 0, children: [None; 8] }];
+    // Internal nodes by depth, for reducing deepest first
+    let mut by_level: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+
+    for bin in bins {
+        let rgb = bin.color.map(|c| c.round().clamp(0.0, 255.0) as u8);
+        let mut node = 0;
+        for (level, internal) in by_level.iter_mut().enumerate() {
+            nodes[node].add(bin);
+            let bit = 7 - level;
+            let child = (((rgb[0] >> bit) & 1) << 2 | ((rgb[1] >> bit) & 1) << 1 | ((rgb[2] >> bit) & 1)) as usize;
+            node = match nodes[node].children[child] {
+                Some(next) => next,
+                None => {
+                    if nodes[node].children.iter().all(Option::is_none) {
+                        internal.push(node);
+                    }
+                    nodes.push(OctreeNode { sum: [0.0; 3], count: 0, children: [None; 8] });
+                    let next = nodes.len() - 1;
+                    nodes[node].children[child] = Some(next);
+                    next
+                }
+            };
+        }
+        nodes[node].add(bin);
+    }
+    let mut leaves = count_leaves(&nodes, 0);
+
+    // Fold whole families of leaves back into their parent, least-used first, until few enough remain;
+    // every deeper level is fully folded before the next one up is touched
+    'reduce: for level in (0..OCTREE_DEPTH).rev() {
+        let mut candidates = std::mem::take(&mut by_level[level]);
+        candidates.sort_by_key(|&node| (nodes[node].count, node));
+        for node in candidates {
+            if leaves <= colors {
+                break 'reduce;
+            }
+            let children = nodes[node].children.iter().flatten().count();
+            nodes[node].children = [None; 8];
+            leaves -= children - 1;
+        }
+    }
+
+    let mut palette = Vec::with_capacity(leaves);
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let entry = &nodes[node];
+        if entry.children.iter().all(Option::is_none) {
+            if entry.count > 0 {
+                palette.push(Rgb(entry.sum.map(|s| (s / entry.count as f64).round() as u8)));
+            }
+        } else {
+            stack.extend(entry.children.iter().flatten());
+        }
+    }
+    palette
+}
+
+fn count_leaves(nodes: &[OctreeNode], root: usize) -> usize {
+    let children: Vec<usize> = nodes[root].children.iter().flatten().copied().collect();
+    if children.is_empty() {
+        1
+    } else {
+        children.into_iter().map(|child| count_leaves(nodes, child)).sum()
+    }
+}
@@ -0,0 +1,43 @@
+use image::{GrayImage, Luma};
+
+use crate::levels::Levels;
+use crate::Ditherer;
+
+// Basic Threshold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Threshold {
+    /// Pixels at or above this level take the lightest output level, as in error diffusion; two-level output only.
+    pub level: u8,
+    /// Gray levels the output may use; with more than two, each pixel takes the nearest.
+    pub levels: Levels,
+}
+
+impl Default for Threshold {
+    fn default() -> Self {
+        Threshold {
+            level: 128,
+            levels: Levels::default(),
+        }
+    }
+}
+
+impl Ditherer for Threshold {
+    fn dither(&self, img: &GrayImage) -> GrayImage {
+        let (width, height) = img.dimensions();
+        let mut dithered = GrayImage::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = img.get_pixel(x, y)[0];
+                let new_val = match self.levels.len() {
+                    2 if pixel >= self.level => self.levels.lightest(),
+                    2 => self.levels.darkest(),
+                    _ => self.levels.nearest(pixel as f32),
+                };
+                dithered.put_pixel(x, y, Luma([new_val]));
+            }
+        }
+
+        dithered
+    }
+}
@@ -0,0 +1,16 @@
+use dithering::ThresholdMap;
+use std::sync::Arc;
+
+#[test]
+fn default_seed_is_generated_once_and_shared() {
+    assert!(Arc::ptr_eq(&ThresholdMap::blue_noise(16, 0), &ThresholdMap::blue_noise(16, 0)));
+}
+
+// Caller-chosen seeds would grow a cache without bound, so each call gets a fresh map
+#[test]
+fn other_seeds_are_regenerated_identically_rather_than_kept() {
+    let (first, second) = (ThresholdMap::blue_noise(16, 7), ThresholdMap::blue_noise(16, 7));
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert_eq!(first, second);
+    assert_ne!(*first, *ThresholdMap::blue_noise(16, 8));
+}
@@ -0,0 +1,56 @@
+use dithering::cmyk::INKS;
+use dithering::{Algorithm, CmykHalftone, CmykOutput, ColorMode, Params, Rendered};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+
+// Hues around the wheel across, lightness down, so every pixel has some gray component
+fn colourful() -> RgbImage {
+    RgbImage::from_fn(48, 32, |x, y| {
+        let shade = 255 - y * 6;
+        let phase = x * 6 % 256;
+        Rgb([shade as u8, (shade * phase / 255) as u8, (shade * (255 - phase) / 255) as u8])
+    })
+}
+
+#[test]
+fn full_gcr_and_ucr_print_neutral_gray_with_black_only() {
+    let screen = CmykHalftone { gcr: 1.0, ucr: 1.0, ..CmykHalftone::default() };
+    for gray in [0, 64, 128, 200, 255] {
+        let [cyan, magenta, yellow, black] = screen.separate(&RgbImage::from_pixel(4, 4, Rgb([gray; 3])));
+        for plate in [cyan, magenta, yellow] {
+            assert!(plate.pixels().all(|p| p[0] == 255), "colour ink under gray {}", gray);
+        }
+        assert!(black.pixels().all(|p| p[0] == gray), "black plate for gray {}", gray);
+    }
+}
+
+#[test]
+fn ucr_defaults_to_gcr() {
+    let img = colourful();
+    let params = Params {
+        color_mode: Some(ColorMode::Cmyk),
+        cmyk_output: Some(CmykOutput::Separations),
+        gcr: Some(0.8),
+        ..Params::default()
+    };
+    let pipeline = Algorithm::Halftone.pipeline(&params).unwrap();
+    let Rendered::Separations(plates) = pipeline.run(&DynamicImage::ImageRgb8(img.clone())) else {
+        panic!("separations asked for");
+    };
+    let matching = CmykHalftone { gcr: 0.8, ucr: 0.8, ..CmykHalftone::default() };
+    assert!(plates == matching.plates(&img), "plates differ from ucr equal to gcr");
+    let fixed = CmykHalftone { gcr: 0.8, ..CmykHalftone::default() };
+    assert!(plates != fixed.plates(&img), "plates match the default ucr instead");
+}
+
+#[test]
+fn composite_lets_each_ink_absorb_its_complement() {
+    let paper = GrayImage::from_pixel(1, 1, Luma([255]));
+    let ink = GrayImage::from_pixel(1, 1, Luma([0]));
+    let expected = [Rgb([0, 255, 255]), Rgb([255, 0, 255]), Rgb([255, 255, 0]), Rgb([0, 0, 0])];
+    for (inked, color) in expected.into_iter().enumerate() {
+        let plates = std::array::from_fn(|plate| if plate == inked { ink.clone() } else { paper.clone() });
+        assert_eq!(*CmykHalftone::composite(&plates).get_pixel(0, 0), color, "{}", INKS[inked]);
+    }
+    let blank = [(); 4].map(|_| paper.clone());
+    assert_eq!(*CmykHalftone::composite(&blank).get_pixel(0, 0), Rgb([255, 255, 255]));
+}
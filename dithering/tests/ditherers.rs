@@ -0,0 +1,78 @@
+use dithering::{Algorithm, KernelEntry, KernelSpec, LevelsSpec, Params, ThresholdMapSpec};
+use image::{GrayImage, Luma};
+
+// The params an algorithm cannot run without; defaults for everything else
+fn required_params(alg: Algorithm) -> Params {
+    match alg {
+        Algorithm::Custom => {
+            let w = KernelEntry::Weight;
+            Params {
+                kernel: Some(KernelSpec {
+                    matrix: vec![
+                        vec![w(0.0), KernelEntry::Marker("*".to_string()), w(7.0)],
+                        vec![w(3.0), w(5.0), w(1.0)],
+                    ],
+                    divisor: Some(16.0),
+                }),
+                ..Params::default()
+            }
+        }
+        Algorithm::ThresholdMap => Params {
+            threshold_map: Some(ThresholdMapSpec::Matrix(vec![vec![0.0, 2.0], vec![3.0, 1.0]])),
+            ..Params::default()
+        },
+        _ => Params::default(),
+    }
+}
+
+// Odd sizes, so no algorithm gets away with assuming whole tiles or even rows
+fn gradient() -> GrayImage {
+    GrayImage::from_fn(37, 23, |x, y| Luma([((x * 7 + y * 3) % 256) as u8]))
+}
+
+#[test]
+fn every_algorithm_keeps_the_image_size() {
+    let img = gradient();
+    for alg in Algorithm::ALL {
+        let out = alg.ditherer(&required_params(alg)).unwrap().dither(&img);
+        assert_eq!(out.dimensions(), img.dimensions(), "{}", alg);
+    }
+}
+
+#[test]
+fn every_algorithm_outputs_only_black_and_white_by_default() {
+    let img = gradient();
+    for alg in Algorithm::ALL {
+        let out = alg.ditherer(&required_params(alg)).unwrap().dither(&img);
+        assert!(out.pixels().all(|p| p[0] == 0 || p[0] == 255), "{} left a gray pixel", alg);
+        assert!(out.pixels().any(|p| p[0] == 0), "{} produced no black", alg);
+        assert!(out.pixels().any(|p| p[0] == 255), "{} produced no white", alg);
+    }
+}
+
+#[test]
+fn every_algorithm_maps_black_and_white_to_themselves() {
+    for value in [0, 255] {
+        let img = GrayImage::from_pixel(16, 16, Luma([value]));
+        for alg in Algorithm::ALL {
+            let out = alg.ditherer(&required_params(alg)).unwrap().dither(&img);
+            assert!(out.pixels().all(|p| p[0] == value), "{} changed solid {}", alg, value);
+        }
+    }
+}
+
+#[test]
+fn every_algorithm_uses_all_of_its_output_levels() {
+    let img = gradient();
+    for alg in Algorithm::ALL {
+        let params = Params {
+            output_levels: Some(LevelsSpec::Values(vec![0, 100, 200, 255])),
+            ..required_params(alg)
+        };
+        let out = alg.ditherer(&params).unwrap().dither(&img);
+        for level in [0, 100, 200, 255] {
+            assert!(out.pixels().any(|p| p[0] == level), "{} never used {}", alg, level);
+        }
+        assert!(out.pixels().all(|p| [0, 100, 200, 255].contains(&p[0])), "{} left an unlisted gray", alg);
+    }
+}
@@ -0,0 +1,108 @@
+use dithering::diffusion::MAX_KERNEL_SIZE;
+use dithering::{kernels, Algorithm, Ditherer, DiffusionOptions, ErrorDiffusion, KernelEntry, KernelError, KernelSpec};
+use dithering::{ParamKind, Params};
+use image::{GrayImage, Luma};
+
+fn w(weight: f32) -> KernelEntry {
+    KernelEntry::Weight(weight)
+}
+
+fn origin() -> KernelEntry {
+    KernelEntry::Marker("*".to_string())
+}
+
+fn spec(matrix: Vec<Vec<KernelEntry>>) -> KernelSpec {
+    KernelSpec { matrix, divisor: None }
+}
+
+#[test]
+fn rejects_weight_on_an_already_processed_pixel() {
+    let kernel = spec(vec![vec![w(1.0), origin(), w(7.0)], vec![w(3.0), w(5.0), w(1.0)]]);
+    assert_eq!(kernel.build().unwrap_err(), KernelError::NonCausal { dx: -1, dy: 0 });
+
+    let above = spec(vec![vec![w(0.0), w(2.0), w(0.0)], vec![w(0.0), origin(), w(7.0)]]);
+    assert_eq!(above.build().unwrap_err(), KernelError::NonCausal { dx: 0, dy: -1 });
+}
+
+#[test]
+fn rejects_weights_that_sum_to_zero() {
+    let kernel = spec(vec![vec![origin(), w(1.0)], vec![w(-1.0), w(0.0)]]);
+    assert_eq!(kernel.build().unwrap_err(), KernelError::ZeroSum);
+
+    let empty = spec(vec![vec![origin(), w(0.0)]]);
+    assert_eq!(empty.build().unwrap_err(), KernelError::ZeroSum);
+}
+
+#[test]
+fn rejects_oversized_matrix() {
+    let mut row = vec![origin()];
+    row.extend((0..MAX_KERNEL_SIZE).map(|_| w(1.0)));
+    let cols = row.len();
+    assert_eq!(spec(vec![row]).build().unwrap_err(), KernelError::Oversized { rows: 1, cols });
+
+    let mut rows = vec![vec![origin(), w(1.0)]];
+    rows.extend((0..MAX_KERNEL_SIZE).map(|_| vec![w(1.0), w(1.0)]));
+    let count = rows.len();
+    assert_eq!(spec(rows).build().unwrap_err(), KernelError::Oversized { rows: count, cols: 2 });
+}
+
+#[test]
+fn rejects_ragged_rows() {
+    let kernel = spec(vec![vec![w(0.0), origin(), w(7.0)], vec![w(3.0), w(5.0)]]);
+    assert_eq!(kernel.build().unwrap_err(), KernelError::Ragged);
+}
+
+#[test]
+fn rejects_markers_other_than_star() {
+    let kernel = spec(vec![vec![KernelEntry::Marker("x".to_string()), w(7.0)]]);
+    assert_eq!(kernel.build().unwrap_err(), KernelError::BadMarker("x".to_string()));
+}
+
+#[test]
+fn rejects_missing_origin() {
+    let kernel = spec(vec![vec![w(0.0), w(7.0)], vec![w(3.0), w(5.0)]]);
+    assert_eq!(kernel.build().unwrap_err(), KernelError::MissingOrigin);
+}
+
+#[test]
+fn rejects_repeated_origin() {
+    let kernel = spec(vec![vec![origin(), w(7.0)], vec![origin(), w(5.0)]]);
+    assert_eq!(kernel.build().unwrap_err(), KernelError::MultipleOrigins);
+}
+
+#[test]
+fn custom_floyd_steinberg_matches_the_builtin_kernel() {
+    let custom = KernelSpec {
+        matrix: vec![vec![w(0.0), origin(), w(7.0)], vec![w(3.0), w(5.0), w(1.0)]],
+        divisor: Some(16.0),
+    };
+    let img = GrayImage::from_fn(64, 48, |x, y| Luma([((x * 4 + y) % 256) as u8]));
+    for serpentine in [false, true] {
+        let options = DiffusionOptions {
+            serpentine,
+            ..DiffusionOptions::default()
+        };
+        let builtin = ErrorDiffusion { kernel: kernels::FLOYD_STEINBERG, options: options.clone() };
+        let custom = ErrorDiffusion { kernel: custom.build().unwrap(), options };
+        assert_eq!(custom.dither(&img), builtin.dither(&img));
+    }
+}
+
+#[test]
+fn builtin_kernels_scan_like_their_algorithm_by_default() {
+    let img = GrayImage::from_fn(64, 48, |x, y| Luma([((x * 4 + y) % 256) as u8]));
+    for alg in Algorithm::ALL {
+        let Some(kernel) = alg.kernel() else { continue };
+        // DiffusionOptions::default keeps the default sRGB levels, so compare with linear light off
+        let srgb = Params { linear_light: Some(false), ..Params::default() };
+        let from_params = alg.ditherer(&srgb).unwrap();
+        let options = DiffusionOptions { serpentine: alg.serpentine_default(), ..DiffusionOptions::default() };
+        assert_eq!(ErrorDiffusion { kernel, options }.dither(&img), from_params.dither(&img), "{alg:?}");
+
+        let documented = alg.params().iter().find_map(|spec| match spec.kind {
+            ParamKind::Boolean { default } if spec.name == "serpentine" => Some(default),
+            _ => None,
+        });
+        assert_eq!(documented, Some(alg.serpentine_default()), "{alg:?}");
+    }
+}
@@ -0,0 +1,84 @@
+use dithering::color::{ciede2000, lab, oklab};
+use dithering::palette::named;
+use dithering::{kernels, ColorMetric, ErrorDiffusion, Matcher, Palette, PaletteDitherer};
+use image::{Rgb, RgbImage};
+use std::time::Instant;
+
+// The metric's distance, computed directly rather than through Matcher's projections
+fn distance(metric: ColorMetric, a: [f32; 3], b: [f32; 3]) -> f32 {
+    let euclidean = |a: [f32; 3], b: [f32; 3]| (0..3).map(|c| (a[c] - b[c]).powi(2)).sum::<f32>().sqrt();
+    match metric {
+        ColorMetric::Rgb => euclidean(a, b),
+        ColorMetric::LumaRgb => {
+            let weights = [0.299, 0.587, 0.114];
+            (0..3).map(|c| weights[c] * (a[c] - b[c]).powi(2)).sum::<f32>().sqrt()
+        }
+        ColorMetric::Cie76 => euclidean(lab(a), lab(b)),
+        ColorMetric::Ciede2000 => ciede2000(lab(a), lab(b)),
+        ColorMetric::Oklab => euclidean(oklab(a), oklab(b)),
+    }
+}
+
+// Every 8-bit step of 15 along each channel, plus a scatter of odd values in between
+fn samples() -> impl Iterator<Item = [f32; 3]> {
+    let grid = (0..=255u32).step_by(15).flat_map(|r| {
+        (0..=255u32).step_by(15).flat_map(move |g| (0..=255u32).step_by(15).map(move |b| [r, g, b]))
+    });
+    let scatter = (0..2000u32).map(|i| [i * 37 % 256, i * 101 % 256, i * 211 % 256]);
+    grid.chain(scatter).map(|rgb| rgb.map(|c| c as f32))
+}
+
+#[test]
+fn every_metric_matches_a_linear_scan() {
+    for named in named::ALL {
+        let palette = Palette::named(named.name).unwrap();
+        let colors: Vec<[f32; 3]> = palette.colors().iter().map(|color| color.0.map(f32::from)).collect();
+        for metric in ColorMetric::ALL {
+            let mut matcher = Matcher::new(&palette, metric);
+            for rgb in samples() {
+                let best = colors.iter().map(|&color| distance(metric, rgb, color)).fold(f32::INFINITY, f32::min);
+                let picked = distance(metric, rgb, colors[matcher.nearest(rgb)]);
+                assert!(
+                    picked - best <= best * 1e-4 + 1e-4,
+                    "{} under {} picked {} for {:?} where {} was possible",
+                    named.name,
+                    metric.name(),
+                    picked,
+                    rgb,
+                    best
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn every_metric_maps_palette_colours_to_themselves() {
+    for named in named::ALL {
+        let palette = Palette::named(named.name).unwrap();
+        for metric in ColorMetric::ALL {
+            let mut matcher = Matcher::new(&palette, metric);
+            for color in palette.colors() {
+                let picked = palette.colors()[matcher.nearest(color.0.map(f32::from))];
+                assert_eq!(picked, *color, "{} under {}", named.name, metric.name());
+            }
+        }
+    }
+}
+
+// Error diffusion asks for a new colour nearly every pixel, so CIEDE2000 must prune the
+// palette rather than scan it: a linear scan of 256 colours runs about a hundred times slower than RGB
+#[test]
+fn ciede2000_stays_fast_on_a_large_palette() {
+    let colors = (0..256u32).map(|i| Rgb([(i * 37 % 256) as u8, (i * 101 % 256) as u8, (i * 211 % 256) as u8]));
+    let palette = Palette::new(colors.collect()).unwrap();
+    let img = RgbImage::from_fn(128, 128, |x, y| Rgb([(x * 2) as u8, (y * 2) as u8, ((x ^ y) * 2) as u8]));
+    let diffusion = ErrorDiffusion::new(kernels::FLOYD_STEINBERG);
+    let time = |metric| {
+        let start = Instant::now();
+        diffusion.dither_palette(&img, &palette, metric);
+        start.elapsed()
+    };
+    let (rgb, ciede2000) = (time(ColorMetric::Rgb), time(ColorMetric::Ciede2000));
+    assert!(ciede2000 < rgb * 30, "ciede2000 took {:?} against {:?} for rgb", ciede2000, rgb);
+}
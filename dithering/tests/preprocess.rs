@@ -0,0 +1,28 @@
+use dithering::Equalize;
+use image::{GrayImage, Luma};
+
+#[test]
+fn flat_image_passes_through_equalisation_unchanged() {
+    let modes = [
+        Equalize::Global,
+        Equalize::Clahe { tile_size: 8, clip_limit: 1.0 },
+        Equalize::Clahe { tile_size: 16, clip_limit: 2.0 },
+        Equalize::Clahe { tile_size: 64, clip_limit: 40.0 },
+    ];
+    for gray in [0, 1, 77, 128, 200, 255] {
+        // Tiles that do not divide the image leave narrower ones along the right and bottom edges
+        let img = GrayImage::from_pixel(45, 30, Luma([gray]));
+        for mode in modes {
+            assert!(mode.apply(&img) == img, "{:?} changed a flat image of gray {}", mode, gray);
+        }
+    }
+}
+
+#[test]
+fn clahe_keeps_the_order_of_grays_within_a_tile() {
+    let img = GrayImage::from_fn(32, 32, |x, y| Luma([(x * 4 + y / 8) as u8]));
+    let equalized = Equalize::Clahe { tile_size: 32, clip_limit: 2.0 }.apply(&img);
+    let mut pairs: Vec<(u8, u8)> = img.pixels().zip(equalized.pixels()).map(|(a, b)| (a[0], b[0])).collect();
+    pairs.sort();
+    assert!(pairs.windows(2).all(|w| w[0].1 <= w[1].1), "CLAHE reordered grays");
+}
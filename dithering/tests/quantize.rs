@@ -0,0 +1,51 @@
+use dithering::Quantizer;
+use image::{Rgb, RgbImage};
+
+// Smooth ramps on every channel, with far more distinct colours than any palette size
+fn gradient() -> RgbImage {
+    RgbImage::from_fn(96, 64, |x, y| Rgb([(x * 8 % 256) as u8, (y * 4) as u8, ((x + y) * 3 % 256) as u8]))
+}
+
+#[test]
+fn flat_image_pads_with_black_or_white() {
+    for quantizer in Quantizer::ALL {
+        let light = quantizer.extract(&RgbImage::from_pixel(16, 16, Rgb([200, 180, 220])), 16, 0);
+        assert_eq!(light.colors(), [Rgb([200, 180, 220]), Rgb([0, 0, 0])], "{}", quantizer.name());
+        let dark = quantizer.extract(&RgbImage::from_pixel(16, 16, Rgb([20, 40, 10])), 16, 0);
+        assert_eq!(dark.colors(), [Rgb([20, 40, 10]), Rgb([255, 255, 255])], "{}", quantizer.name());
+    }
+}
+
+#[test]
+fn fewer_distinct_colours_than_palette_size_keeps_each_once() {
+    let colors = [Rgb([255, 0, 0]), Rgb([0, 128, 255]), Rgb([240, 240, 16])];
+    let img = RgbImage::from_fn(30, 10, |x, _| colors[x as usize / 10]);
+    for quantizer in Quantizer::ALL {
+        let palette = quantizer.extract(&img, 16, 0);
+        assert_eq!(palette.len(), colors.len(), "{}", quantizer.name());
+        for color in colors {
+            assert!(palette.colors().contains(&color), "{} lost {:?}", quantizer.name(), color);
+        }
+    }
+}
+
+#[test]
+fn k_means_is_deterministic_for_a_seed() {
+    let img = gradient();
+    for seed in [0, 1, 0xDEAD_BEEF] {
+        let first = Quantizer::KMeans.extract(&img, 8, seed);
+        assert_eq!(first, Quantizer::KMeans.extract(&img, 8, seed), "seed {}", seed);
+    }
+}
+
+#[test]
+fn every_quantizer_returns_at_most_the_colours_asked_for() {
+    let img = gradient();
+    for quantizer in Quantizer::ALL {
+        for colors in [2, 3, 5, 16, 64, 256] {
+            let palette = quantizer.extract(&img, colors, 0);
+            assert!(palette.len() <= colors, "{} gave {} for {}", quantizer.name(), palette.len(), colors);
+            assert!(palette.len() >= 2, "{} gave {} for {}", quantizer.name(), palette.len(), colors);
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use dithering::{Algorithm, Params};
+use image::{GrayImage, Luma};
+
+// A gray exactly at the threshold turns white, whichever algorithm applies it
+#[test]
+fn threshold_level_itself_turns_white() {
+    let img = GrayImage::from_pixel(1, 1, Luma([100]));
+    for alg in [Algorithm::Threshold, Algorithm::FloydSteinberg, Algorithm::Atkinson] {
+        let params = Params {
+            threshold: Some(100),
+            linear_light: Some(false),
+            ..Params::default()
+        };
+        let out = alg.ditherer(&params).unwrap().dither(&img);
+        assert_eq!(out.get_pixel(0, 0)[0], 255, "{}", alg);
+
+        let below = GrayImage::from_pixel(1, 1, Luma([99]));
+        assert_eq!(alg.ditherer(&params).unwrap().dither(&below).get_pixel(0, 0)[0], 0, "{}", alg);
+    }
+}
@@ -0,0 +1,33 @@
+use base64::{engine::general_purpose, Engine as _};
+use dithering::ordered::MAX_MAP_SIZE;
+use dithering::{MapError, ThresholdMapSpec};
+use image::codecs::png::PngEncoder;
+use image::ColorType;
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let pixels = vec![128; (width * height) as usize];
+    PngEncoder::new(&mut bytes).encode(&pixels, width, height, ColorType::L8).unwrap();
+    bytes
+}
+
+fn image_map(bytes: &[u8]) -> ThresholdMapSpec {
+    ThresholdMapSpec::Image(general_purpose::STANDARD.encode(bytes))
+}
+
+#[test]
+fn accepts_image_up_to_the_limit() {
+    assert!(image_map(&png(MAX_MAP_SIZE, 2)).build().is_ok());
+}
+
+#[test]
+fn rejects_oversized_image_from_its_header() {
+    let width = MAX_MAP_SIZE + 1;
+    assert_eq!(image_map(&png(width, 2)).build().unwrap_err(), MapError::Oversized { width, height: 2 });
+
+    // Cut off at the start of the pixel data: nothing left to decode, so the size must come from the header
+    let mut header = png(width, 2);
+    let idat = header.windows(4).position(|chunk| chunk == b"IDAT").unwrap();
+    header.truncate(idat + 4);
+    assert_eq!(image_map(&header).build().unwrap_err(), MapError::Oversized { width, height: 2 });
+}
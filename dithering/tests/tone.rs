@@ -0,0 +1,127 @@
+use dithering::color::srgb8_to_linear;
+use dithering::{Algorithm, LevelsSpec, Params};
+use image::{GrayImage, Luma};
+
+fn mean(img: &GrayImage) -> f64 {
+    img.pixels().map(|p| p[0] as f64).sum::<f64>() / (img.width() * img.height()) as f64
+}
+
+// Mean light emitted by the columns `x0..x1`, 0 to 1
+fn mean_light(img: &GrayImage, x0: u32, x1: u32) -> f64 {
+    let pixels = (x0..x1).flat_map(|x| (0..img.height()).map(move |y| (x, y)));
+    let total: f64 = pixels.map(|(x, y)| srgb8_to_linear(img.get_pixel(x, y)[0]) as f64).sum();
+    total / ((x1 - x0) * img.height()) as f64
+}
+
+// Horizontal ramp from `from` to `to`, repeated on every row
+fn ramp(from: u8, to: u8, width: u32, height: u32) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, _| {
+        let t = x as f64 / (width - 1) as f64;
+        Luma([(from as f64 + t * (to as f64 - from as f64)).round() as u8])
+    })
+}
+
+// Atkinson deliberately drops a quarter of the error, so it is left out
+fn tone_preserving() -> impl Iterator<Item = Algorithm> {
+    Algorithm::ALL
+        .into_iter()
+        .filter(|alg| alg.kernel().is_some() && *alg != Algorithm::Atkinson)
+}
+
+// The mean of sRGB code values is only kept when dithering on them directly
+fn srgb() -> Params {
+    Params {
+        linear_light: Some(false),
+        ..Params::default()
+    }
+}
+
+#[test]
+fn error_diffusion_keeps_mean_on_full_ramp() {
+    let img = ramp(0, 255, 256, 64);
+    for alg in tone_preserving() {
+        let out = alg.ditherer(&srgb()).unwrap().dither(&img);
+        let drift = (mean(&out) - mean(&img)).abs();
+        assert!(drift < 1.0, "{} drifted by {:.3}", alg, drift);
+    }
+}
+
+// Error pushed off the bottom and right edges is lost, hence the tolerance
+#[test]
+fn error_diffusion_keeps_mean_near_black_and_white() {
+    for (from, to) in [(0, 16), (239, 255)] {
+        let img = ramp(from, to, 256, 256);
+        for alg in tone_preserving() {
+            let out = alg.ditherer(&srgb()).unwrap().dither(&img);
+            let drift = (mean(&out) - mean(&img)).abs();
+            assert!(drift < 0.75, "{} drifted by {:.3} on {}..{}", alg, drift, from, to);
+        }
+    }
+}
+
+#[test]
+fn multi_level_output_keeps_mean() {
+    let img = ramp(0, 255, 256, 64);
+    let params = Params {
+        output_levels: Some(LevelsSpec::Count(4)),
+        ..srgb()
+    };
+    let out = Algorithm::FloydSteinberg.ditherer(&params).unwrap().dither(&img);
+    assert!((mean(&out) - mean(&img)).abs() < 1.0);
+}
+
+// Black and white the levels cannot reach must come out as the nearest level, with no
+// error carried over the edge between them
+#[test]
+fn error_diffusion_stays_inside_narrow_levels() {
+    let img = GrayImage::from_fn(64, 32, |x, _| Luma([if x < 32 { 0 } else { 255 }]));
+    for linear_light in [false, true] {
+        let params = Params {
+            output_levels: Some(LevelsSpec::Values(vec![64, 192])),
+            linear_light: Some(linear_light),
+            ..Params::default()
+        };
+        let out = Algorithm::FloydSteinberg.ditherer(&params).unwrap().dither(&img);
+        for (x, _, p) in out.enumerate_pixels() {
+            assert_eq!(p[0], if x < 32 { 64 } else { 192 }, "column {} in linear light {}", x, linear_light);
+        }
+    }
+}
+
+// Perceived tone follows the light emitted, so in linear light every stretch of a
+// gradient should come out as bright as it went in
+#[test]
+fn linear_light_keeps_gradient_brightness() {
+    let img = ramp(0, 255, 512, 128);
+    let bands = (0..512).step_by(32).map(|x| (x, x + 32));
+    for alg in tone_preserving().chain([Algorithm::BlueNoise]) {
+        let out = alg.ditherer(&Params::default()).unwrap().dither(&img);
+        for (x0, x1) in bands.clone() {
+            let drift = (mean_light(&out, x0, x1) - mean_light(&img, x0, x1)).abs();
+            assert!(drift < 0.02, "{} drifted by {:.4} over columns {}..{}", alg, drift, x0, x1);
+        }
+    }
+}
+
+#[test]
+fn linear_light_keeps_gradient_brightness_with_levels() {
+    let img = ramp(0, 255, 512, 128);
+    let params = Params {
+        output_levels: Some(LevelsSpec::Values(vec![0, 80, 170, 255])),
+        ..Params::default()
+    };
+    let out = Algorithm::FloydSteinberg.ditherer(&params).unwrap().dither(&img);
+    for x0 in (0..512).step_by(32) {
+        let drift = (mean_light(&out, x0, x0 + 32) - mean_light(&img, x0, x0 + 32)).abs();
+        assert!(drift < 0.01, "drifted by {:.4} over columns {}..{}", drift, x0, x0 + 32);
+    }
+}
+
+// Dithering sRGB values spreads code values, not light: a 50% pattern of black and
+// white emits far more light than sRGB gray 128, so midtones come out too light
+#[test]
+fn srgb_dithering_lightens_midtones() {
+    let img = ramp(96, 160, 256, 128);
+    let out = Algorithm::FloydSteinberg.ditherer(&srgb()).unwrap().dither(&img);
+    assert!(mean_light(&out, 0, 256) > mean_light(&img, 0, 256) + 0.1);
+}